@@ -10,7 +10,8 @@ use color_eyre::{
 use itertools::Itertools as _;
 use nix::errno::Errno;
 use rust_bindgen_fuse::{
-    FilePermissions, FileType, Filesystem, GetfattrRetVal, OpenFlags, OpenRetVal, ReadRetVal,
+    AccessMode, ConnectionInfo, DirEntry, DirEntryName, FilePermissions, FileType, Filesystem,
+    FuseConfig, GetfattrRetVal, MountOptions, OpenFlags, OpenRetVal, ReadRetVal, ReadSize,
     ReaddirRetVal, Stat, TypedModeBuilder,
 };
 use tracing::{Level, error};
@@ -23,6 +24,15 @@ const FILES: [&str; 1] = ["hello.txt"];
 pub struct HelloFS;
 
 impl Filesystem for HelloFS {
+    type FileHandle = ();
+
+    const ADD_DOT_ENTRIES: bool = true;
+
+    fn init(&self, _conn: &mut ConnectionInfo, cfg: &mut FuseConfig) {
+        // the content never changes, so let the kernel keep it cached
+        cfg.kernel_cache = true;
+    }
+
     fn getattr(&self, path: &Path) -> Result<GetfattrRetVal, nix::Error> {
         if path == "/" {
             Ok(GetfattrRetVal {
@@ -56,30 +66,48 @@ impl Filesystem for HelloFS {
     fn readdir(&self, path: &Path) -> Result<ReaddirRetVal, nix::Error> {
         if path == "/" {
             Ok(ReaddirRetVal {
-                entries: FILES.into_iter().map(|s| s.to_owned()).collect_vec(),
+                entries: FILES
+                    .into_iter()
+                    .map(|name| DirEntryName::new(name).map(DirEntry::from))
+                    .try_collect()?,
             })
         } else {
             Err(Errno::ENOENT)
         }
     }
 
-    fn open(&self, _path: &Path, _flags: OpenFlags) -> Result<OpenRetVal, nix::Error> {
-        todo!("currently unused in API")
+    fn open(&self, path: &Path, flags: OpenFlags) -> Result<OpenRetVal<()>, nix::Error> {
+        if flags.access_mode() != AccessMode::ReadOnly {
+            return Err(Errno::EACCES);
+        }
+        if path == HELLO_PATH {
+            Ok(OpenRetVal {
+                file_handle: (),
+                fuse_file_info: None,
+            })
+        } else {
+            Err(Errno::ENOENT)
+        }
     }
 
-    fn read(&self, path: &Path, n: u32, offset: isize) -> Result<ReadRetVal, nix::Error> {
+    fn read(
+        &self,
+        path: &Path,
+        _file_handle: Option<&()>,
+        _size: ReadSize,
+        offset: u64,
+    ) -> Result<ReadRetVal, nix::Error> {
         if path == HELLO_PATH {
+            // the crate truncates the content to `size`
             Ok(ReadRetVal {
-                content: if let Ok(offset) = usize::try_from(offset) {
-                    if offset >= HELLO_CONTENT.len() {
+                content: match usize::try_from(offset) {
+                    Ok(offset) if offset < HELLO_CONTENT.len() => {
+                        HELLO_CONTENT.as_bytes()[offset..].to_owned()
+                    }
+                    _ => {
                         error!("offset out of bounds, returning 0 bytes read");
                         vec![]
-                    } else {
-                        HELLO_CONTENT.as_bytes()[offset..].to_owned()
                     }
-                } else {
-                    error!("negative offset is not supported");
-                    Err(Errno::ENOENT)?
                 },
             })
         } else {
@@ -105,6 +133,6 @@ fn main() -> Result<()> {
 
     let fs = HelloFS;
 
-    let _fuse = rust_bindgen_fuse::fuse_main(fs, mount_point, std::env::args())?;
+    let _fuse = rust_bindgen_fuse::fuse_main(fs, mount_point, &MountOptions::default())?;
     Ok(())
 }
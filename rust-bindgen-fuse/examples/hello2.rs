@@ -10,28 +10,48 @@ use color_eyre::{Result, eyre::bail};
 use itertools::Itertools as _;
 use nix::errno::Errno;
 use rust_bindgen_fuse::{
-    FilePermissions, FileType, Filesystem, GetfattrRetVal, OpenFlags, OpenRetVal, ReadRetVal,
-    ReaddirRetVal, Stat, TypedModeBuilder,
+    AccessMode, DirEntry, DirEntryName, FilePermissions, FileType, Filesystem, FsOption,
+    FuseFileInfo, GetfattrRetVal, LoopConfig, MountOptions, OpenFlags, OpenRetVal, ReadRetVal,
+    ReadSize, ReaddirRetVal, Stat, TypedModeBuilder,
 };
 use tracing::{Level, debug, error, instrument, trace};
 use tracing_subscriber::EnvFilter;
 
+/// Whether the content of a file may change between reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Content {
+    /// Opened with `keep_cache`, so the kernel may serve it from its page cache.
+    Stable,
+    /// Opened with `direct_io`, so every read reaches us and the size doesn't have to be known in `getattr`.
+    Dynamic,
+}
+
 type FileEntry = (
     &'static str,
+    Content,
     Box<dyn Send + Sync + 'static + Fn() -> String>,
 );
 
 static FILES: LazyLock<[FileEntry; 6]> = LazyLock::new(|| {
     [
-        ("/hello.txt", Box::new(|| "Hello world!".into())),
-        ("/pid", Box::new(|| std::process::id().to_string())),
+        (
+            "/hello.txt",
+            Content::Stable,
+            Box::new(|| "Hello world!".into()),
+        ),
+        (
+            "/pid",
+            Content::Dynamic,
+            Box::new(|| std::process::id().to_string()),
+        ),
         (
             "/time",
+            Content::Dynamic,
             Box::new(|| format!("{}", chrono::Local::now().format("%c"))),
         ),
-        ("/foo/bar/baz", Box::new(|| "blub".into())),
-        ("/foo/bar/qux", Box::new(|| "blub".into())),
-        ("/foo/fux", Box::new(|| "blub".into())),
+        ("/foo/bar/baz", Content::Stable, Box::new(|| "blub".into())),
+        ("/foo/bar/qux", Content::Stable, Box::new(|| "blub".into())),
+        ("/foo/fux", Content::Stable, Box::new(|| "blub".into())),
     ]
 });
 
@@ -94,7 +114,7 @@ static ROOT: LazyLock<Dir> = LazyLock::new(|| {
         subdirs: vec![],
     };
 
-    for (path, _) in FILES.iter() {
+    for (path, _, _) in FILES.iter() {
         let path = if !path.starts_with('/') {
             String::from("/") + path
         } else {
@@ -120,14 +140,14 @@ static ROOT: LazyLock<Dir> = LazyLock::new(|| {
     root
 });
 
-fn gen_file_entry(path: &str, content: &str) -> Result<GetfattrRetVal, Errno> {
+fn gen_file_entry(path: &str, size: usize) -> Result<GetfattrRetVal, Errno> {
     let stat = Stat::new_simple(
         TypedModeBuilder::builder()
             .file_type(FileType::RegularFile)
             .permissions(FilePermissions::new(0o444).unwrap())
             .build(),
         1,
-        content.len() as i64,
+        size as i64,
     );
 
     let stat = match stat {
@@ -211,14 +231,22 @@ fn find_dir<'a>(path: &Path, root: &'a Dir) -> Result<&'a Dir, Errno> {
 pub struct HelloFS;
 
 impl Filesystem for HelloFS {
+    type FileHandle = ();
+
+    const ADD_DOT_ENTRIES: bool = true;
+
     #[instrument]
     fn getattr(&self, path: &Path) -> Result<GetfattrRetVal, nix::Error> {
         let path_str = path.to_str().expect("always unicode");
 
-        if let Some((_, content_fn)) = FILES.iter().find(|(path_, _)| path == *path_) {
+        if let Some((_, content, content_fn)) = FILES.iter().find(|(path_, _, _)| path == *path_) {
             debug!("found path inside FILES array");
-            let content = content_fn();
-            return gen_file_entry(path_str, &content);
+            // dynamic files are opened with `direct_io`, so there's no need to generate their content up front
+            let size = match content {
+                Content::Stable => content_fn().len(),
+                Content::Dynamic => 0,
+            };
+            return gen_file_entry(path_str, size);
         };
 
         // otherwise this must be a directory (or non-existent)
@@ -232,40 +260,64 @@ impl Filesystem for HelloFS {
             entries: dir
                 .subdirs
                 .iter()
-                .map(|d| d.name.clone())
-                .chain(dir.files.clone().into_iter())
-                .collect(),
+                .map(|d| (&d.name, FileType::Directory))
+                .chain(dir.files.iter().map(|f| (f, FileType::RegularFile)))
+                .map(|(name, file_type)| {
+                    Ok(DirEntry::with_file_type(
+                        DirEntryName::new(name.as_str())?,
+                        file_type,
+                    ))
+                })
+                .collect::<Result<_, Errno>>()?,
         })
     }
 
-    fn open(&self, _path: &Path, _flags: OpenFlags) -> Result<OpenRetVal, nix::Error> {
-        todo!("currently unused in API")
+    fn open(&self, path: &Path, flags: OpenFlags) -> Result<OpenRetVal<()>, nix::Error> {
+        if flags.access_mode() != AccessMode::ReadOnly {
+            return Err(Errno::EACCES);
+        }
+        if let Some((_, content, _)) = FILES.iter().find(|(p, _, _)| path == *p) {
+            Ok(OpenRetVal {
+                file_handle: (),
+                fuse_file_info: Some(FuseFileInfo {
+                    direct_io: *content == Content::Dynamic,
+                    keep_cache: *content == Content::Stable,
+                    ..Default::default()
+                }),
+            })
+        } else {
+            Err(Errno::ENOENT)
+        }
     }
 
     #[instrument]
-    fn read(&self, path: &Path, n: u32, offset: isize) -> Result<ReadRetVal, nix::Error> {
+    fn read(
+        &self,
+        path: &Path,
+        _file_handle: Option<&()>,
+        n: ReadSize,
+        offset: u64,
+    ) -> Result<ReadRetVal, nix::Error> {
         let path = path.to_str().expect("unicode…");
-        if let Some((_, content_fn)) = FILES.iter().find(|(p, _)| *p == path) {
+        if let Some((_, _, content_fn)) = FILES.iter().find(|(p, _, _)| *p == path) {
             let content = content_fn();
             Ok(ReadRetVal {
-                content: if let Ok(offset) = usize::try_from(offset) {
-                    if offset >= content.len() {
-                        error!("offset out of bounds, returning 0 bytes read");
-                        vec![]
-                    } else {
+                content: match usize::try_from(offset) {
+                    Ok(offset) if offset < content.len() => {
                         let content = &content.as_bytes()[offset..];
-                        let range_end = (content.len()).min(n as usize);
-                        // truncate to return a maximum of `n` bytes, to not overflow the user buffer
+                        let range_end = (content.len()).min(n.get());
+                        // truncate to return a maximum of `n` bytes, so the crate doesn't have to
                         let mut content = content[..range_end].to_owned();
                         // we only serve text files, so unconditionally append a newline if we read til the end.
-                        if range_end < n as usize && content.last().is_none_or(|c| *c != b'\n') {
+                        if range_end < n.get() && content.last().is_none_or(|c| *c != b'\n') {
                             content.push(b'\n')
                         }
                         content
                     }
-                } else {
-                    error!("negative offset is not supported");
-                    Err(Errno::ENOENT)?
+                    _ => {
+                        error!("offset out of bounds, returning 0 bytes read");
+                        vec![]
+                    }
                 },
             })
         } else {
@@ -274,6 +326,17 @@ impl Filesystem for HelloFS {
     }
 }
 
+/// Our own `-o` options, everything else is passed to libfuse.
+const OPTIONS: &[FsOption<LoopConfig>] = &[FsOption::value(
+    "threads",
+    "N",
+    "maximum number of worker threads (10)",
+    |loop_config, value| {
+        loop_config.max_threads = value.parse()?;
+        Ok(())
+    },
+)];
+
 fn main() -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -283,13 +346,22 @@ fn main() -> Result<()> {
         )
         .init();
 
-    let args: Vec<_> = args().collect();
-    let [_, mount_point] = args.as_slice() else {
-        eprintln!("Usage: hello `mount_point`");
+    let mut loop_config = LoopConfig::default();
+    let parsed = rust_bindgen_fuse::parse_fs_options(args(), OPTIONS, &mut loop_config)?;
+    if parsed.show_help {
+        return rust_bindgen_fuse::print_help(OPTIONS);
+    }
+    let [mount_point] = parsed.positional.as_slice() else {
+        eprintln!("Usage: hello2 [-o threads=N] `mount_point`");
         bail!("invalid args")
     };
 
     let fs = HelloFS;
-    let _fuse = rust_bindgen_fuse::fuse_main(fs, mount_point, std::env::args())?;
+    let options = MountOptions::builder()
+        .fsname("hello2")
+        .args(parsed.fuse_args)
+        .build();
+    // the files are independent of each other, so serve them from several threads
+    rust_bindgen_fuse::fuse_main_mt(fs, mount_point, &options, loop_config)?;
     Ok(())
 }
@@ -37,12 +37,20 @@
 
 use std::{
     ffi::{CStr, CString, c_char, c_void},
-    fmt,
-    mem::ManuallyDrop,
-    ops::Range,
+    fmt, iter,
+    mem::MaybeUninit,
+    num::NonZeroU32,
+    ops::RangeInclusive,
+    os::unix::ffi::OsStrExt as _,
     path::{Path, PathBuf},
     ptr,
-    sync::Arc,
+    str::FromStr,
+    sync::{
+        Arc, Mutex, PoisonError, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use color_eyre::{
@@ -55,7 +63,7 @@ use itertools::Itertools as _;
 use nix::{Error as Errno, libc};
 use singleton_registry::define_registry;
 use thiserror::Error;
-use tracing::{debug, instrument};
+use tracing::{debug, error, instrument, warn};
 use typed_builder::TypedBuilder;
 
 #[allow(clippy::all)]
@@ -101,8 +109,9 @@ macro_rules! try_errno {
 }
 
 macro_rules! bitflag_accessor {
-    ($inner_type:ty, $name:ident, $val:path) => {
-        fn $name(&self) -> bool {
+    ($vis:vis $inner_type:ty, $name:ident, $val:path) => {
+        #[must_use]
+        $vis fn $name(&self) -> bool {
             self.0 & $val as $inner_type != 0
         }
     };
@@ -150,38 +159,313 @@ impl Stat {
         Self(stat)
     }
     /// `mode` - bitmask for the typical modes/permission under *nix (ugw, director etc)
+    ///
+    /// Everything else is zero (owned by root, last modified at the epoch). Use [`TypedStatBuilder`] for more control.
     pub fn new_simple(mode: FileMode, n_link: u64, size: i64) -> Result<Self> {
-        Ok(Self(libfuse::stat {
-            st_nlink: n_link,
-            st_size: size,
-            st_mode: mode.0, // TODO (important) provide API for flags
-            st_dev: 0,
-            st_ino: 0,
-            st_uid: 0,
-            st_gid: 0,
+        let size = u64::try_from(size).wrap_err("size must not be negative")?;
+        TypedStatBuilder::builder()
+            .mode(mode)
+            .n_link(n_link)
+            .size(size)
+            .build()
+    }
+
+    #[must_use]
+    pub fn inner(&self) -> &libfuse::stat {
+        &self.0
+    }
+
+    pub fn file_type(&self) -> Result<FileType, FileModeError> {
+        FileType::try_from(self.0.st_mode & libfuse::S_IFMT)
+    }
+
+    #[must_use]
+    pub fn permissions(&self) -> FilePermissions {
+        // masked to 0o777, so this can't truncate
+        FilePermissions((self.0.st_mode & 0o777) as u16)
+    }
+
+    /// A negative `st_size` (only possible through [`Stat::new_unchecked`]) is reported as 0.
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        u64::try_from(self.0.st_size).unwrap_or(0)
+    }
+
+    /// `None` if the timestamp isn't representable as `SystemTime`.
+    #[must_use]
+    pub fn accessed(&self) -> Option<SystemTime> {
+        system_time_from_timespec(self.0.st_atim)
+    }
+
+    /// `None` if the timestamp isn't representable as `SystemTime`.
+    #[must_use]
+    pub fn modified(&self) -> Option<SystemTime> {
+        system_time_from_timespec(self.0.st_mtim)
+    }
+
+    /// Time of the last status change. `None` if the timestamp isn't representable as `SystemTime`.
+    #[must_use]
+    pub fn changed(&self) -> Option<SystemTime> {
+        system_time_from_timespec(self.0.st_ctim)
+    }
+}
+
+impl From<nix::sys::stat::FileStat> for Stat {
+    fn from(file_stat: nix::sys::stat::FileStat) -> Self {
+        Self(libfuse::stat {
+            st_dev: file_stat.st_dev,
+            st_ino: file_stat.st_ino,
+            st_nlink: file_stat.st_nlink,
+            st_mode: file_stat.st_mode,
+            st_uid: file_stat.st_uid,
+            st_gid: file_stat.st_gid,
             __pad0: Default::default(),
+            st_rdev: file_stat.st_rdev,
+            st_size: file_stat.st_size,
+            st_blksize: file_stat.st_blksize,
+            st_blocks: file_stat.st_blocks,
+            st_atim: libfuse::timespec {
+                tv_sec: file_stat.st_atime,
+                tv_nsec: file_stat.st_atime_nsec,
+            },
+            st_mtim: libfuse::timespec {
+                tv_sec: file_stat.st_mtime,
+                tv_nsec: file_stat.st_mtime_nsec,
+            },
+            st_ctim: libfuse::timespec {
+                tv_sec: file_stat.st_ctime,
+                tv_nsec: file_stat.st_ctime_nsec,
+            },
+            __glibc_reserved: Default::default(),
+        })
+    }
+}
+
+/// Fails if a value doesn't fit into `libfuse::stat`, which can't happen for metadata of real files on 64 bit
+/// Linux.
+impl TryFrom<std::fs::Metadata> for Stat {
+    type Error = Report;
 
-            st_rdev: 0,
-            st_blksize: 0,
-            st_blocks: 0,
+    fn try_from(metadata: std::fs::Metadata) -> Result<Self, Self::Error> {
+        use std::os::unix::fs::MetadataExt as _;
+
+        Ok(Self(libfuse::stat {
+            st_dev: metadata.dev(),
+            st_ino: metadata.ino(),
+            st_nlink: metadata.nlink(),
+            st_mode: metadata.mode(),
+            st_uid: metadata.uid(),
+            st_gid: metadata.gid(),
+            __pad0: Default::default(),
+            st_rdev: metadata.rdev(),
+            st_size: metadata.size().try_into().wrap_err("size too big")?,
+            st_blksize: metadata
+                .blksize()
+                .try_into()
+                .wrap_err("block size too big")?,
+            st_blocks: metadata
+                .blocks()
+                .try_into()
+                .wrap_err("block count too big")?,
             st_atim: libfuse::timespec {
-                tv_sec: 0,
-                tv_nsec: 0,
+                tv_sec: metadata.atime(),
+                tv_nsec: metadata.atime_nsec(),
             },
             st_mtim: libfuse::timespec {
-                tv_sec: 0,
-                tv_nsec: 0,
+                tv_sec: metadata.mtime(),
+                tv_nsec: metadata.mtime_nsec(),
             },
             st_ctim: libfuse::timespec {
-                tv_sec: 0,
-                tv_nsec: 0,
+                tv_sec: metadata.ctime(),
+                tv_nsec: metadata.ctime_nsec(),
             },
             __glibc_reserved: Default::default(),
         }))
     }
+}
+
+/// Checked builder for [`Stat`], `build()` returns a `Result<Stat>`.
+///
+/// Unset ids, numbers and timestamps default to zero, the timestamps to [`UNIX_EPOCH`].
+#[derive(TypedBuilder)]
+#[builder(build_method(into = Result<Stat>))]
+pub struct TypedStatBuilder {
+    mode: FileMode,
+    #[builder(default = 1)]
+    n_link: u64,
+    #[builder(default)]
+    size: u64,
+
+    #[builder(default)]
+    uid: u32,
+    #[builder(default)]
+    gid: u32,
+    #[builder(default)]
+    ino: u64,
+    /// Device containing the file.
+    #[builder(default)]
+    dev: u64,
+    /// Device represented by the file, only valid for block and character devices.
+    #[builder(default)]
+    rdev: u64,
+
+    #[builder(default = UNIX_EPOCH)]
+    atime: SystemTime,
+    #[builder(default = UNIX_EPOCH)]
+    mtime: SystemTime,
+    #[builder(default = UNIX_EPOCH)]
+    ctime: SystemTime,
+
+    /// Preferred I/O size.
+    #[builder(default)]
+    block_size: u64,
+    /// Number of 512 byte blocks allocated. Conflicts with `blocks_from_size`.
+    #[builder(default, setter(strip_option))]
+    blocks: Option<u64>,
+    /// Compute `blocks` from `size`, assuming the file isn't sparse.
+    #[builder(setter(strip_bool(fallback = toggle_blocks_from_size)))]
+    blocks_from_size: bool,
+}
+
+impl From<TypedStatBuilder> for Result<Stat> {
+    fn from(
+        TypedStatBuilder {
+            mode,
+            n_link,
+            size,
+            uid,
+            gid,
+            ino,
+            dev,
+            rdev,
+            atime,
+            mtime,
+            ctime,
+            block_size,
+            blocks,
+            blocks_from_size,
+        }: TypedStatBuilder,
+    ) -> Self {
+        let file_type = mode.file_type();
+        if rdev != 0 && !matches!(file_type, FileType::BlockDevice | FileType::CharacterDevice) {
+            bail!("rdev set for {file_type:?}, which is no device");
+        }
+        let blocks = match (blocks, blocks_from_size) {
+            (Some(_), true) => bail!("`blocks` and `blocks_from_size` are mutually exclusive"),
+            (Some(blocks), false) => blocks,
+            (None, true) => size.div_ceil(512),
+            (None, false) => 0,
+        };
+
+        Ok(Stat(libfuse::stat {
+            st_dev: dev,
+            st_ino: ino,
+            st_nlink: n_link,
+            st_mode: mode.0,
+            st_uid: uid,
+            st_gid: gid,
+            __pad0: Default::default(),
+            st_rdev: rdev,
+            st_size: size.try_into().wrap_err("size too big")?,
+            st_blksize: block_size.try_into().wrap_err("block size too big")?,
+            st_blocks: blocks.try_into().wrap_err("block count too big")?,
+            st_atim: timespec_from_system_time(atime).wrap_err("atime out of range")?,
+            st_mtim: timespec_from_system_time(mtime).wrap_err("mtime out of range")?,
+            st_ctim: timespec_from_system_time(ctime).wrap_err("ctime out of range")?,
+            __glibc_reserved: Default::default(),
+        }))
+    }
+}
+
+/// Checked builder for [`StatFs`], `build()` returns a `Result<StatFs>`.
+///
+/// All block counts are in units of `block_size`. Unset inode counts default to zero.
+#[derive(TypedBuilder)]
+#[builder(build_method(into = Result<StatFs>))]
+pub struct TypedStatFsBuilder {
+    #[builder(default = 4096)]
+    block_size: u64,
+    blocks: u64,
+    blocks_free: u64,
+    /// Free blocks available to unprivileged users, at most `blocks_free`. Defaults to `blocks_free`.
+    #[builder(default, setter(strip_option))]
+    blocks_available: Option<u64>,
+    #[builder(default)]
+    files: u64,
+    #[builder(default)]
+    files_free: u64,
+    /// Maximum length of a file name.
+    #[builder(default = u64::from(libc::NAME_MAX.unsigned_abs()))]
+    name_max: u64,
+}
+
+impl From<TypedStatFsBuilder> for Result<StatFs> {
+    fn from(
+        TypedStatFsBuilder {
+            block_size,
+            blocks,
+            blocks_free,
+            blocks_available,
+            files,
+            files_free,
+            name_max,
+        }: TypedStatFsBuilder,
+    ) -> Self {
+        let blocks_available = blocks_available.unwrap_or(blocks_free);
+        if block_size == 0 {
+            bail!("block size must not be zero");
+        }
+        if name_max == 0 {
+            bail!("maximum name length must not be zero");
+        }
+        if blocks_free > blocks || blocks_available > blocks_free {
+            bail!(
+                "inconsistent block counts: {blocks_available} available <= {blocks_free} free <= {blocks} total violated"
+            );
+        }
+        if files_free > files {
+            bail!("inconsistent inode counts: {files_free} free > {files} total");
+        }
+
+        // SAFETY: `statvfs` is plain old data, all-zero is a valid value
+        let mut statvfs: libfuse::statvfs = unsafe { std::mem::zeroed() };
+        statvfs.f_bsize = block_size;
+        statvfs.f_frsize = block_size;
+        statvfs.f_blocks = blocks;
+        statvfs.f_bfree = blocks_free;
+        statvfs.f_bavail = blocks_available;
+        statvfs.f_files = files;
+        statvfs.f_ffree = files_free;
+        statvfs.f_favail = files_free;
+        statvfs.f_namemax = name_max;
+        Ok(StatFs(statvfs))
+    }
+}
+
+/// File system statistics as reported by `statfs(2)`/`df(1)`.
+///
+/// Refer to <https://www.man7.org/linux/man-pages/man3/statvfs.3.html> for infos about the underlying values.
+#[derive(Debug, Clone, Copy, Into, Deref)]
+pub struct StatFs(libfuse::statvfs);
+impl StatFs {
+    #[must_use]
+    pub unsafe fn new_unchecked(statvfs: libfuse::statvfs) -> Self {
+        Self(statvfs)
+    }
 
+    /// A file system of unknown (zero) capacity, with sane block size and name length so tools like `df(1)` don't
+    /// choke on it.
     #[must_use]
-    pub fn inner(&self) -> &libfuse::stat {
+    pub fn unknown() -> Self {
+        TypedStatFsBuilder::builder()
+            .blocks(0)
+            .blocks_free(0)
+            .build()
+            .expect("constant values are consistent")
+    }
+
+    #[must_use]
+    pub fn inner(&self) -> &libfuse::statvfs {
         &self.0
     }
 }
@@ -199,9 +483,29 @@ pub enum FileType {
     Socket = libfuse::S_IFSOCK,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Always holds a valid file type, construct it via the builders or `TryFrom<u32>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Into)]
 pub struct FileMode(FileModeRepr);
 
+impl FileMode {
+    #[must_use]
+    pub fn file_type(self) -> FileType {
+        FileType::try_from(self.0 & libfuse::S_IFMT)
+            .expect("invariant: `FileMode` is only constructed with a valid file type")
+    }
+
+    #[must_use]
+    pub fn permissions(self) -> FilePermissions {
+        // masked to 0o777, so this can't truncate
+        FilePermissions((self.0 & 0o777) as u16)
+    }
+
+    bitflag_accessor!(pub FileModeRepr, setuid, libfuse::S_ISUID);
+    bitflag_accessor!(pub FileModeRepr, setgid, libfuse::S_ISGID);
+    // sticky bit
+    bitflag_accessor!(pub FileModeRepr, vtx_flag, libfuse::S_ISVTX);
+}
+
 #[derive(Debug, Builder)]
 pub struct RuntimeModeBuilder {
     file_type: FileType,
@@ -240,7 +544,7 @@ impl FilePermissions {
     pub fn new(value: u16) -> Result<Self, OutOfRangeError<u16>> {
         if value > 0o777 {
             return Err(OutOfRangeError {
-                range: 0..0o777,
+                range: 0..=0o777,
                 value,
             });
         }
@@ -249,12 +553,251 @@ impl FilePermissions {
 }
 
 #[derive(Debug, Error)]
-#[error("Argument out of range: '{value}'. Must be between '{}' and '{}'", range.start, range.end)]
+#[error("Argument out of range: '{value}'. Must be in '{}..={}'", range.start(), range.end())]
 pub struct OutOfRangeError<T: fmt::Display> {
-    range: Range<T>,
+    range: RangeInclusive<T>,
     value: T,
 }
 
+// symbolic modes, as in `ls -l` and `chmod(1)`
+
+const SPECIAL_BITS: FileModeRepr = libfuse::S_ISUID | libfuse::S_ISGID | libfuse::S_ISVTX;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ModeParseError {
+    #[error("Not a valid `ls -l` mode: '{0}'")]
+    InvalidLsMode(String),
+    #[error("Not a valid symbolic `chmod` clause: '{0}'")]
+    InvalidClause(String),
+    #[error("setuid, setgid and sticky bits can't be represented by `FilePermissions`: '{0}'")]
+    SpecialBits(String),
+    #[error(transparent)]
+    FileMode(#[from] FileModeError),
+}
+
+/// Parses the 9 permission characters of `ls -l` (e.g. `rwsr-xr-T`) into permission and special bits.
+fn parse_ls_permissions(s: &str) -> Result<FileModeRepr, ModeParseError> {
+    let invalid = || ModeParseError::InvalidLsMode(s.to_owned());
+    let chars = s.chars().collect_vec();
+    if chars.len() != 9 {
+        return Err(invalid());
+    }
+
+    let mut bits = 0;
+    // user, group, other: (offset, special bit, its letter)
+    for (triplet, (offset, special, letter)) in chars.chunks(3).zip([
+        (6, libfuse::S_ISUID, 's'),
+        (3, libfuse::S_ISGID, 's'),
+        (0, libfuse::S_ISVTX, 't'),
+    ]) {
+        let &[read, write, exec] = triplet else {
+            unreachable!("length checked above");
+        };
+        bits |= match read {
+            'r' => 0o4 << offset,
+            '-' => 0,
+            _ => return Err(invalid()),
+        };
+        bits |= match write {
+            'w' => 0o2 << offset,
+            '-' => 0,
+            _ => return Err(invalid()),
+        };
+        bits |= match exec {
+            'x' => 0o1 << offset,
+            '-' => 0,
+            // lowercase: special bit and execute, uppercase: only the special bit
+            c if c == letter => special | 0o1 << offset,
+            c if c == letter.to_ascii_uppercase() => special,
+            _ => return Err(invalid()),
+        };
+    }
+    Ok(bits)
+}
+
+/// Inverse of [`parse_ls_permissions`], ignores the file type bits.
+fn format_ls_permissions(bits: FileModeRepr) -> String {
+    let mut s = String::with_capacity(9);
+    for (offset, special, letter) in [
+        (6, libfuse::S_ISUID, 's'),
+        (3, libfuse::S_ISGID, 's'),
+        (0, libfuse::S_ISVTX, 't'),
+    ] {
+        s.push(if bits & 0o4 << offset != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 << offset != 0 { 'w' } else { '-' });
+        s.push(match (bits & 0o1 << offset != 0, bits & special != 0) {
+            (true, true) => letter,
+            (false, true) => letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    s
+}
+
+/// Applies comma separated `chmod(1)` clauses like `u+x,go-w,a=r` to the permission and special bits of `base`.
+///
+/// `X` sets execute only for directories or if any execute bit is already set. Unlike `chmod(1)` the umask is
+/// ignored if no `ugoa` is given, so `+x` is equivalent to `a+x`. Copying permissions (`g=u`) isn't supported.
+fn apply_symbolic_clauses(
+    base: FileModeRepr,
+    is_dir: bool,
+    clauses: &str,
+) -> Result<FileModeRepr, ModeParseError> {
+    let mut bits = base;
+    for clause in clauses.split(',') {
+        let invalid = || ModeParseError::InvalidClause(clause.to_owned());
+
+        let actions_start = clause.find(|c| !"ugoa".contains(c)).ok_or_else(invalid)?;
+        let (who, actions) = clause.split_at(actions_start);
+        let who_mask = who.chars().fold(0, |mask, c| {
+            mask | match c {
+                'u' => libfuse::S_ISUID | libfuse::S_IRWXU,
+                'g' => libfuse::S_ISGID | libfuse::S_IRWXG,
+                'o' => libfuse::S_ISVTX | libfuse::S_IRWXO,
+                _ => SPECIAL_BITS | 0o777,
+            }
+        });
+        let who_mask = if who.is_empty() {
+            SPECIAL_BITS | 0o777
+        } else {
+            who_mask
+        };
+
+        let mut actions = actions.chars().peekable();
+        while let Some(op) = actions.next() {
+            let mut perms = 0;
+            while let Some(c) = actions.next_if(|c| !"+-=".contains(*c)) {
+                perms |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    'X' if is_dir || bits & 0o111 != 0 => 0o111,
+                    'X' => 0,
+                    's' => libfuse::S_ISUID | libfuse::S_ISGID,
+                    't' => libfuse::S_ISVTX,
+                    _ => return Err(invalid()),
+                };
+            }
+            let perms = perms & who_mask;
+            match op {
+                '+' => bits |= perms,
+                '-' => bits &= !perms,
+                '=' => bits = (bits & !who_mask) | perms,
+                _ => return Err(invalid()),
+            }
+        }
+    }
+    Ok(bits)
+}
+
+impl FilePermissions {
+    /// Applies symbolic `chmod(1)` clauses (e.g. `u+x,go-w`) to these permissions. Fails if a clause would set
+    /// setuid, setgid or sticky bit.
+    pub fn apply_symbolic(self, clauses: &str) -> Result<Self, ModeParseError> {
+        let bits = apply_symbolic_clauses(self.0.into(), false, clauses)?;
+        if bits & SPECIAL_BITS != 0 {
+            return Err(ModeParseError::SpecialBits(clauses.to_owned()));
+        }
+        // masked to 0o777 above, so this can't truncate
+        Ok(Self(bits as u16))
+    }
+
+    /// `ls -l` form, e.g. `rwxr-xr-x`.
+    #[must_use]
+    pub fn ls(self) -> String {
+        format_ls_permissions(self.0.into())
+    }
+}
+
+/// Characters of the `ls -l` permission form, see [`parse_ls_permissions`].
+const LS_PERMISSION_CHARS: &str = "rwxsStT-";
+
+/// Accepts the `ls -l` form (`rw-r--r--`) or symbolic `chmod(1)` clauses (`u=rwx,go=rx`), applied to `---------`.
+impl FromStr for FilePermissions {
+    type Err = ModeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.chars().all(|c| LS_PERMISSION_CHARS.contains(c))
+            && let Ok(bits) = parse_ls_permissions(s)
+        {
+            if bits & SPECIAL_BITS != 0 {
+                return Err(ModeParseError::SpecialBits(s.to_owned()));
+            }
+            // no special bits, so this can't truncate
+            return Ok(Self(bits as u16));
+        }
+        // not necessarily invalid, e.g. `-rw-rw-rw` are three clauses with an implied `a`
+        Self(0).apply_symbolic(s)
+    }
+}
+
+impl FileType {
+    /// The file type letter of `ls -l`.
+    #[must_use]
+    pub fn ls_char(self) -> char {
+        match self {
+            Self::BlockDevice => 'b',
+            Self::CharacterDevice => 'c',
+            Self::Fifo => 'p',
+            Self::RegularFile => '-',
+            Self::Directory => 'd',
+            Self::SymbolicLink => 'l',
+            Self::Socket => 's',
+        }
+    }
+}
+
+impl FileMode {
+    /// Applies symbolic `chmod(1)` clauses (e.g. `u+s,go-w`) to the permission and special bits, keeping the file
+    /// type.
+    pub fn apply_symbolic(self, clauses: &str) -> Result<Self, ModeParseError> {
+        let is_dir = self.file_type() == FileType::Directory;
+        let bits = apply_symbolic_clauses(self.0 & !libfuse::S_IFMT, is_dir, clauses)?;
+        Ok(Self((self.0 & libfuse::S_IFMT) | bits))
+    }
+}
+
+/// Accepts the `ls -l` form including the file type (e.g. `drwxr-sr-x`), or symbolic `chmod(1)` clauses applied to
+/// a regular file without permissions (`----------`). Use [`FileMode::apply_symbolic`] for other bases.
+impl FromStr for FileMode {
+    type Err = ModeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let file_type = match chars.next() {
+            Some('b') => Some(FileType::BlockDevice),
+            Some('c') => Some(FileType::CharacterDevice),
+            Some('p') => Some(FileType::Fifo),
+            Some('-') => Some(FileType::RegularFile),
+            Some('d') => Some(FileType::Directory),
+            Some('l') => Some(FileType::SymbolicLink),
+            Some('s') => Some(FileType::Socket),
+            _ => None,
+        };
+        let permissions = chars.as_str();
+        if let Some(file_type) = file_type
+            && permissions.chars().all(|c| LS_PERMISSION_CHARS.contains(c))
+            && let Ok(bits) = parse_ls_permissions(permissions)
+        {
+            return Ok(Self::try_from(file_type as FileModeRepr | bits)?);
+        }
+        Self(libfuse::S_IFREG).apply_symbolic(s)
+    }
+}
+
+/// `ls -l` form, e.g. `drwxr-xr-x`.
+impl fmt::Display for FileMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            self.file_type().ls_char(),
+            format_ls_permissions(self.0)
+        )
+    }
+}
+
 impl From<TypedModeBuilder> for FileMode {
     fn from(
         TypedModeBuilder {
@@ -295,596 +838,4621 @@ impl From<RuntimeModeBuilder> for FileMode {
     }
 }
 
-pub struct FuseFileInfo(libfuse::fuse_file_info);
+impl TryFrom<FileModeRepr> for FileType {
+    type Error = FileModeError;
 
-pub struct OpenFlags(i32);
+    /// Expects only the file type bits (`S_IFMT`) to be set.
+    fn try_from(value: FileModeRepr) -> Result<Self, Self::Error> {
+        Ok(match value {
+            libfuse::S_IFBLK => Self::BlockDevice,
+            libfuse::S_IFCHR => Self::CharacterDevice,
+            libfuse::S_IFIFO => Self::Fifo,
+            libfuse::S_IFREG => Self::RegularFile,
+            libfuse::S_IFDIR => Self::Directory,
+            libfuse::S_IFLNK => Self::SymbolicLink,
+            libfuse::S_IFSOCK => Self::Socket,
+            _ => return Err(FileModeError::UnknownFileType(value)),
+        })
+    }
+}
 
-impl OpenFlags {
-    bitflag_accessor!(i32, append, OpenFlag::Append);
-    bitflag_accessor!(i32, readonly, OpenFlag::Readonly);
-    bitflag_accessor!(i32, writeonly, OpenFlag::Writeonly);
-    bitflag_accessor!(i32, read_plus_write, OpenFlag::ReadPlusWrite);
-    bitflag_accessor!(i32, truncate, OpenFlag::Truncate);
+/// Decodes a raw `mode_t`, as libfuse passes it to e.g. `chmod` or `create`.
+impl TryFrom<FileModeRepr> for FileMode {
+    type Error = FileModeError;
+
+    fn try_from(value: FileModeRepr) -> Result<Self, Self::Error> {
+        let known_bits =
+            libfuse::S_IFMT | libfuse::S_ISUID | libfuse::S_ISGID | libfuse::S_ISVTX | 0o777;
+        if value & !known_bits != 0 {
+            return Err(FileModeError::UnknownBits(value));
+        }
+        FileType::try_from(value & libfuse::S_IFMT)?;
+        Ok(Self(value))
+    }
 }
 
-/// This is repr(i32) because the target bitset (fuse_file_info::flags) and the `libc` constants are also i32.
-#[repr(i32)]
-enum OpenFlag {
-    Append = libc::O_APPEND,
-    Readonly = libc::O_RDONLY,
-    Writeonly = libc::O_WRONLY,
-    ReadPlusWrite = libc::O_RDWR,
-    Truncate = libc::O_TRUNC,
-    // TODO (maybe) exhaust
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FileModeError {
+    #[error("Unknown file type bits: 0o{0:o}")]
+    UnknownFileType(FileModeRepr),
+    #[error("Mode has bits set besides file type, permissions and setuid/setgid/sticky: 0o{0:o}")]
+    UnknownBits(FileModeRepr),
 }
 
-// type GetattrHook = fn(
-//     path: *const i8,
-//     stat_out: *mut libfuse::stat,
-//     fuse_file_info_out: *mut libfuse::fuse_file_info,
-// ) -> i32;
-// type OpenHook = fn(path: *const c_char, fuse_file_info_out: *mut libfuse::fuse_file_info) -> i32;
-// type ReadHook = fn(
-//     path: *const c_char,
-//     buf_out: *mut c_char,
-//     n: size_t,
-//     offset: libfuse::off_t,
-//     fuse_file_info_out: *mut libfuse::fuse_file_info,
-// ) -> i32;
+/// One of the two timestamps passed to [`Filesystem::utimens`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtimeSpec {
+    /// Set the timestamp to the current time (`UTIME_NOW`).
+    Now,
+    /// Leave the timestamp unchanged (`UTIME_OMIT`).
+    Omit,
+    Time(SystemTime),
+}
 
-// ///
-// ///
-// type ReaddirHook = fn(
-//     path: *const c_char,
-//     data_ptr: *mut c_void,
-//     filler_fn: libfuse::fuse_fill_dir_t,
-//     offset: libfuse::off_t,
-//     fuse_file_info_out: *mut libfuse::fuse_file_info,
-//     readdir_flags: libfuse::fuse_readdir_flags,
-// ) -> i32;
+impl TryFrom<libfuse::timespec> for UtimeSpec {
+    type Error = Errno;
 
-pub struct GetfattrRetVal {
-    pub stat: Stat,
+    fn try_from(value: libfuse::timespec) -> Result<Self, Self::Error> {
+        Ok(match value.tv_nsec {
+            libc::UTIME_NOW => Self::Now,
+            libc::UTIME_OMIT => Self::Omit,
+            _ => Self::Time(system_time_from_timespec(value).ok_or(Errno::EINVAL)?),
+        })
+    }
 }
 
-// TODO (maybe) use Cow and <T: AsRef<str>> params to let user choose wether to pass owned Strings or references.
-pub struct ReaddirRetVal {
-    pub entries: Vec<String>,
-    // seems to only be set from `open`, `opendir`, `create` - https://libfuse.github.io/doxygen/structfuse__file__info.html#afcff4109f1c8fb7ff51f18500496271d
-    //pub fuse_file_info: Option<FuseFileInfo>,
+/// Returns `None` if `tv_nsec` is out of range or the time isn't representable as `SystemTime`.
+fn system_time_from_timespec(
+    libfuse::timespec { tv_sec, tv_nsec }: libfuse::timespec,
+) -> Option<SystemTime> {
+    let nanos = u32::try_from(tv_nsec)
+        .ok()
+        .filter(|nanos| *nanos < 1_000_000_000)?;
+    let since_epoch = Duration::from_secs(tv_sec.unsigned_abs());
+    let whole_seconds = if tv_sec >= 0 {
+        UNIX_EPOCH.checked_add(since_epoch)?
+    } else {
+        UNIX_EPOCH.checked_sub(since_epoch)?
+    };
+    // `tv_nsec` always counts forward, even for negative `tv_sec`
+    whole_seconds.checked_add(Duration::from_nanos(nanos.into()))
 }
 
-pub struct OpenRetVal {
-    pub fuse_file_info: Option<FuseFileInfo>,
+/// Fails if the time is too far from the epoch for `tv_sec`.
+fn timespec_from_system_time(time: SystemTime) -> Result<libfuse::timespec> {
+    let (tv_sec, tv_nsec) = match time.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => (
+            i64::try_from(since_epoch.as_secs())?,
+            since_epoch.subsec_nanos(),
+        ),
+        Err(e) => {
+            // `tv_nsec` always counts forward, even for negative `tv_sec`
+            let before_epoch = e.duration();
+            let secs = -i64::try_from(before_epoch.as_secs())?;
+            match before_epoch.subsec_nanos() {
+                0 => (secs, 0),
+                nanos => (secs - 1, 1_000_000_000 - nanos),
+            }
+        }
+    };
+    Ok(libfuse::timespec {
+        tv_sec,
+        tv_nsec: tv_nsec.into(),
+    })
 }
 
-pub struct ReadRetVal {
-    pub content: Vec<u8>,
-}
+/// Number of bytes the kernel asked [`Filesystem::read`] for. Never zero, and at most `i32::MAX`, so the
+/// number of bytes read always fits libfuse's return value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Into, Deref)]
+pub struct ReadSize(u32);
 
-pub trait Filesystem: Send + Sync + 'static {
-    fn getattr(&self, path: &Path) -> Result<GetfattrRetVal, Errno>;
-    fn readdir(&self, path: &Path) -> Result<ReaddirRetVal, Errno>;
-    fn open(&self, path: &Path, flags: OpenFlags) -> Result<OpenRetVal, Errno>;
-    fn read(&self, path: &Path, size: u32, offset: isize) -> Result<ReadRetVal, Errno>;
+impl ReadSize {
+    pub fn new(size: usize) -> Result<Self, OutOfRangeError<usize>> {
+        let range = 1..=i32::MAX.unsigned_abs() as usize;
+        if !range.contains(&size) {
+            return Err(OutOfRangeError { range, value: size });
+        }
+        // checked above, fits into u32
+        Ok(Self(size as u32))
+    }
+
+    #[must_use]
+    pub fn get(self) -> usize {
+        self.0 as usize
+    }
 }
 
-/*unsafe extern "C" {
-impl<FS: Filesystem> FSImplForC<FS> {
-    pub fn getfattr(path: &Path, stat: &mut Stat, fuse_file_info: &mut FuseFileInfo) -> i32 {
-        let fs = match state::get::<FS>() {
-            Ok(fs) => fs,
-            Err(e) => {
-                error!("registry error on `{}`: {e:#}", type_name::<FS>());
-                return -EFAULT;
-            }
-        };
+/// Per-open options the file system can set from [`Filesystem::open`] and [`Filesystem::create`], see
+/// <https://libfuse.github.io/doxygen/structfuse__file__info.html>.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FuseFileInfo {
+    /// Bypass the page cache for this file. Reads return exactly what [`Filesystem::read`] returns, so `st_size`
+    /// doesn't need to be known up front.
+    pub direct_io: bool,
+    /// Don't invalidate the page cache on open, for content that doesn't change behind the kernel's back.
+    pub keep_cache: bool,
+    /// The file isn't seekable, e.g. a stream.
+    pub nonseekable: bool,
+    /// Don't call [`Filesystem::flush`] on `close(2)` of this file.
+    pub noflush: bool,
+    /// Allow concurrent direct writes to the same file, only has an effect together with `direct_io`.
+    pub parallel_direct_writes: bool,
+}
 
-        return -(fs.getattr(path) as i32);
+impl FuseFileInfo {
+    fn apply_to(self, fuse_file_info: &mut libfuse::fuse_file_info) {
+        let Self {
+            direct_io,
+            keep_cache,
+            nonseekable,
+            noflush,
+            parallel_direct_writes,
+        } = self;
+        fuse_file_info.set_direct_io(direct_io.into());
+        fuse_file_info.set_keep_cache(keep_cache.into());
+        fuse_file_info.set_nonseekable(nonseekable.into());
+        fuse_file_info.set_noflush(noflush.into());
+        fuse_file_info.set_parallel_direct_writes(parallel_direct_writes.into());
     }
-    Option<
 }
-    }
-*/
 
-// INVALID cannot coerce closure to c-style call? even if we could, there would be no state captured,
-// so we're back to square one (just using static trampoline functions)
-// #[allow(non_snake_case)]
-// pub fn passthrough_getattr(fs: impl Filesystem) -> GettattrFuseFn {
-//     |_, _, _| { /* fs.getattr(…) */ }
-// }
+/// The `flags` of `open(2)`: an [`AccessMode`] plus flag bits. Bits unknown to this crate are kept, see
+/// [`OpenFlags::unknown_bits`].
+///
+/// Note that the kernel handles some flags itself and never passes them on (e.g. `O_CREAT`, `O_EXCL`, `O_NOCTTY`
+/// and `O_TRUNC` for `open`, see `fuse_operations::open`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Into)]
+pub struct OpenFlags(i32);
 
-// OUTLOOK pass global static pointer to `Filesystem` struct, instead of
-// looking this up in a registry. Pro: multiple filesystem per impl allowed.
-// Cons: since rust doesn't allow pointers as const generic, we have to (unsafely)
-// cast between usize and pointer.
-// has libfuse compatible signature, can be passed inside `fuse_operations`
+impl OpenFlags {
+    bitflag_accessor!(pub i32, append, OpenFlag::Append);
+    bitflag_accessor!(pub i32, async_io, OpenFlag::Async);
+    bitflag_accessor!(pub i32, cloexec, OpenFlag::Cloexec);
+    bitflag_accessor!(pub i32, create, OpenFlag::Create);
+    bitflag_accessor!(pub i32, direct, OpenFlag::Direct);
+    bitflag_accessor!(pub i32, directory, OpenFlag::Directory);
+    bitflag_accessor!(pub i32, exclusive, OpenFlag::Exclusive);
+    bitflag_accessor!(pub i32, largefile, OpenFlag::Largefile);
+    bitflag_accessor!(pub i32, noatime, OpenFlag::Noatime);
+    bitflag_accessor!(pub i32, noctty, OpenFlag::Noctty);
+    bitflag_accessor!(pub i32, nofollow, OpenFlag::Nofollow);
+    bitflag_accessor!(pub i32, nonblock, OpenFlag::Nonblock);
+    bitflag_accessor!(pub i32, path, OpenFlag::Path);
+    bitflag_accessor!(pub i32, truncate, OpenFlag::Truncate);
+    // `O_DSYNC` is a subset of `O_SYNC`, so `sync()` implies `dsync()`
+    bitflag_accessor!(pub i32, dsync, OpenFlag::Dsync);
 
-///
-pub unsafe extern "C" fn getattr<FS: Filesystem>(
-    path: *const i8,
-    stat_out: *mut libfuse::stat,
-    _fuse_file_info_out: *mut libfuse::fuse_file_info,
-) -> i32 {
-    // Safety
-    ensure_errno!(!path.is_null(), Errno::EINVAL);
-    ensure_errno!(!stat_out.is_null(), Errno::EINVAL);
-    //   ensure_errno!(!_fuse_file_info_out.is_null(), Errno::EINVAL);
-    ensure_errno!(path.is_aligned(), Errno::EINVAL);
-    ensure_errno!(stat_out.is_aligned(), Errno::EINVAL);
-    //    ensure_errno!(_fuse_file_info_out.is_aligned(), Errno::EINVAL);
-
-    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+    /// `O_SYNC`, which includes the `O_DSYNC` bit, so this checks for all of its bits.
+    #[must_use]
+    pub fn sync(&self) -> bool {
+        self.0 & OpenFlag::Sync as i32 == OpenFlag::Sync as i32
+    }
 
-    // THESIS https://doc.rust-lang.org/edition-guide/rust-2024/unsafe-op-in-unsafe-fn.html
-    // safe wrapping of params
-    // SAFETY: we check invariants at the function start
-    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+    /// `O_TMPFILE`, which includes the `O_DIRECTORY` bit, so this checks for all of its bits.
+    #[must_use]
+    pub fn tmpfile(&self) -> bool {
+        self.0 & OpenFlag::Tmpfile as i32 == OpenFlag::Tmpfile as i32
+    }
 
-    debug!("enter: getfattr('{}')", path.to_string_lossy());
-    let result = try_errno!(call_into_user_code::<FS, _>("getfattr", || fs.getattr(&path)));
-    let GetfattrRetVal { stat } = result;
-    debug!("return: getfattr => {}", path.to_string_lossy());
+    /// Linux has no `O_EXEC`/`O_SEARCH`, they are modelled after musl, which defines both as `O_PATH`.
+    #[must_use]
+    pub fn access_mode(&self) -> AccessMode {
+        if self.path() {
+            return if self.directory() {
+                AccessMode::Search
+            } else {
+                AccessMode::Exec
+            };
+        }
+        match self.0 & libc::O_ACCMODE {
+            libc::O_RDONLY => AccessMode::ReadOnly,
+            libc::O_WRONLY => AccessMode::WriteOnly,
+            libc::O_RDWR => AccessMode::ReadWrite,
+            // `3` is a Linux special, requiring both read and write permissions without allowing either
+            // (see `open(2)`). Treat it like `O_RDWR`, which requires the same permissions.
+            _ => AccessMode::ReadWrite,
+        }
+    }
 
-    // SAFETY: we assume that the two outptrs received by libfuse are not dangling. We can check for alignment and
-    // non-null-ity, but invalid memory addresses will not be caught.
-    unsafe {
-        *stat_out = *stat;
+    /// All bits besides the access mode and the flags known to [`OpenFlags`].
+    #[must_use]
+    pub fn unknown_bits(&self) -> i32 {
+        self.0 & !libc::O_ACCMODE & !OpenFlag::ALL.iter().fold(0, |all, flag| all | *flag as i32)
     }
+}
 
-    return 0;
+/// The access mode of [`OpenFlags`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    /// `O_PATH` on a non-directory
+    Exec,
+    /// `O_PATH | O_DIRECTORY`
+    Search,
 }
 
-/// * `buf` - (buffer to pass to filler fn?? TODO)
-/// * `filler_fn` - function to call once per directory entry? TODO
-/// * `offset` - should be ignorable since we only support complete dir listing in one go? TODO
-#[tracing::instrument]
-pub unsafe extern "C" fn readdir<FS: Filesystem>(
-    path: *const c_char,
-    data_ptr: *mut c_void,
-    filler_fn: libfuse::fuse_fill_dir_t,
-    _offset: libfuse::off_t,
-    fuse_file_info_out: *mut libfuse::fuse_file_info,
-    _readdir_flags: libfuse::fuse_readdir_flags,
-) -> i32 {
-    let Some(filler_fn) = filler_fn else {
-        bail_errno!("`filler_fn` must not be null", Errno::EINVAL);
-    };
+impl AccessMode {
+    #[must_use]
+    pub fn is_readable(self) -> bool {
+        matches!(self, Self::ReadOnly | Self::ReadWrite)
+    }
 
-    ensure_errno!(!path.is_null(), Errno::EINVAL);
-    ensure_errno!(!fuse_file_info_out.is_null(), Errno::EINVAL);
-    ensure_errno!(path.is_aligned(), Errno::EINVAL);
-    ensure_errno!(fuse_file_info_out.is_aligned(), Errno::EINVAL);
+    #[must_use]
+    pub fn is_writable(self) -> bool {
+        matches!(self, Self::WriteOnly | Self::ReadWrite)
+    }
+}
 
-    // since we don't use `data_ptr` besides passing it to `filler_fn`, we can ignore invariants.
-    //ensure_errno!(data_ptr.is_aligned(), Errno::EINVAL);
-    //ensure_errno!(!data_ptr.is_null(), Errno::EINVAL);
+/// `libc::O_LARGEFILE` is 0 on 64 bit targets, but the kernel still sets the bit. Its value is arch specific.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const O_LARGEFILE: i32 = 0o100_000;
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+const O_LARGEFILE: i32 = 0o400_000;
+#[cfg(any(target_arch = "powerpc", target_arch = "powerpc64"))]
+const O_LARGEFILE: i32 = 0o200_000;
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    target_arch = "powerpc",
+    target_arch = "powerpc64"
+)))]
+const O_LARGEFILE: i32 = libc::O_LARGEFILE;
 
-    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+/// This is repr(i32) because the target bitset (fuse_file_info::flags) and the `libc` constants are also i32.
+///
+/// The access mode (`O_RDONLY`, `O_WRONLY`, `O_RDWR`) is no flag, see [`AccessMode`].
+#[derive(Debug, Clone, Copy)]
+#[repr(i32)]
+enum OpenFlag {
+    Append = libc::O_APPEND,
+    Async = libc::O_ASYNC,
+    Cloexec = libc::O_CLOEXEC,
+    Create = libc::O_CREAT,
+    Direct = libc::O_DIRECT,
+    Directory = libc::O_DIRECTORY,
+    Dsync = libc::O_DSYNC,
+    Exclusive = libc::O_EXCL,
+    Largefile = O_LARGEFILE,
+    Noatime = libc::O_NOATIME,
+    Noctty = libc::O_NOCTTY,
+    Nofollow = libc::O_NOFOLLOW,
+    Nonblock = libc::O_NONBLOCK,
+    Path = libc::O_PATH,
+    Sync = libc::O_SYNC,
+    Tmpfile = libc::O_TMPFILE,
+    Truncate = libc::O_TRUNC,
+}
 
-    // SAFETY: we check invariants at the function start
-    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+impl OpenFlag {
+    const ALL: [Self; 17] = [
+        Self::Append,
+        Self::Async,
+        Self::Cloexec,
+        Self::Create,
+        Self::Direct,
+        Self::Directory,
+        Self::Dsync,
+        Self::Exclusive,
+        Self::Largefile,
+        Self::Noatime,
+        Self::Noctty,
+        Self::Nofollow,
+        Self::Nonblock,
+        Self::Path,
+        Self::Sync,
+        Self::Tmpfile,
+        Self::Truncate,
+    ];
+}
 
-    debug!("enter: readdir('{}')", path.to_string_lossy());
-    let result = try_errno!(call_into_user_code::<FS, _>("readdir", || fs.readdir(&path)));
-    let ReaddirRetVal { entries } = result;
-    debug!("return: readdir => {entries:?}");
+/// Flags of `renameat2(2)`, as passed to [`Filesystem::rename`].
+///
+/// `RENAME_EXCHANGE` and `RENAME_NOREPLACE` are mutually exclusive, other flags are rejected with `EINVAL` before
+/// reaching user code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenameFlags(u32);
 
-    for entry in entries {
-        let entry_as_c_string = try_errno!(CString::new(entry.clone()).map_err(|e| {
-            (
-                format!("converting dir entry '{entry}' into a C string: {e:#}"),
-                Errno::EIO,
-            )
-        }));
-        debug!(?path, "filling entry '{entry}'");
-        let fill_result = unsafe {
-            filler_fn(
-                data_ptr,
-                entry_as_c_string.as_ptr(),
-                ptr::null(), /* setting `stat` struct to NULL, as per `hello.c` */
-                0,           /*: offset */
-                // …_PLUS => let kernel fill inode cache by announcing that the stat param is fully set
-                libfuse::fuse_fill_dir_flags_FUSE_FILL_DIR_DEFAULTS,
-            )
-        };
+impl RenameFlags {
+    // atomically exchange `from` and `to`. Both must exist.
+    bitflag_accessor!(pub u32, exchange, RenameFlag::Exchange);
+    // don't overwrite `to` if it exists, fail with `EEXIST` instead.
+    bitflag_accessor!(pub u32, noreplace, RenameFlag::NoReplace);
+}
 
-        if fill_result != 0 {
-            bail_errno!(
-                format!("filler_fn returned non-zero for '{entry}': {fill_result}"),
-                Errno::EIO
-            );
+impl TryFrom<u32> for RenameFlags {
+    type Error = Errno;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        let known = RenameFlag::Exchange as u32 | RenameFlag::NoReplace as u32;
+        if value & !known != 0 {
+            return Err(Errno::EINVAL);
         }
+        let flags = Self(value);
+        // see `renameat2(2)`
+        if flags.exchange() && flags.noreplace() {
+            return Err(Errno::EINVAL);
+        }
+        Ok(flags)
     }
-
-    return 0;
 }
 
-/// FUSE docs:
-///
-/// ```quote
-///
-/// Open a file
-///
-/// Open flags are available in fi->flags. The following rules apply.
-///
-/// - Creation (O_CREAT, O_EXCL, O_NOCTTY) flags will be filtered out / handled by the kernel.
-/// - Access modes (O_RDONLY, O_WRONLY, O_RDWR, O_EXEC, O_SEARCH) should be used by the filesystem to check if the operation is permitted. If the -o default_permissions mount option is given, this check is already done by the kernel before calling open() and may thus be omitted by the filesystem.
-/// - When writeback caching is enabled, the kernel may send read requests even for files opened with O_WRONLY. The filesystem should be prepared to handle this.
-/// - When writeback caching is disabled, the filesystem is expected to properly handle the O_APPEND flag and ensure that each write is appending to the end of the file.
-/// - When writeback caching is enabled, the kernel will handle O_APPEND. However, unless all changes to the file come through the kernel this will not work reliably. The filesystem should thus either ignore the O_APPEND flag (and let the kernel handle it), or return an error (indicating that reliably O_APPEND is not available).
-///
-/// Filesystem may store an arbitrary file handle (pointer, index, etc) in fi->fh, and use this in other all other file operations (read, write, flush, release, fsync).
-///
-/// Filesystem may also implement stateless file I/O and not store anything in fi->fh.
-///
-/// There are also some flags (direct_io, keep_cache) which the filesystem may set in fi, to change the way the file is opened. See fuse_file_info structure in <fuse_common.h> for more details.
-///
-/// If this request is answered with an error code of ENOSYS and FUSE_CAP_NO_OPEN_SUPPORT is set in fuse_conn_info.capable, this is treated as success and future calls to open will also succeed without being sent to the filesystem process.
-///
-/// Definition at line 486 of file fuse.h.
-///
-/// ```
-#[instrument]
-pub unsafe extern "C" fn open<FS: Filesystem>(
-    path: *const i8,
-    fuse_file_info: *mut libfuse::fuse_file_info,
-) -> i32 {
-    ensure_errno!(!path.is_null(), Errno::EINVAL);
-    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
-    ensure_errno!(path.is_aligned(), Errno::EINVAL);
-    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+/// This is repr(u32) because `fuse_operations::rename` passes the flags as `unsigned int`.
+#[repr(u32)]
+enum RenameFlag {
+    Exchange = libc::RENAME_EXCHANGE,
+    NoReplace = libc::RENAME_NOREPLACE,
+}
 
-    let flags = unsafe { OpenFlags((*fuse_file_info).flags) };
+/// Namespace of an extended attribute, see `xattr(7)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XattrNamespace {
+    User,
+    Trusted,
+    Security,
+    System,
+}
 
-    // we only support readonly access
-    ensure_errno!(!flags.readonly(), Errno::EACCES);
+impl XattrNamespace {
+    const ALL: [Self; 4] = [Self::User, Self::Trusted, Self::Security, Self::System];
 
-    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+    #[must_use]
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::User => "user.",
+            Self::Trusted => "trusted.",
+            Self::Security => "security.",
+            Self::System => "system.",
+        }
+    }
+}
 
-    // currently this is a NOOP
-    debug!("enter: open('{}') // NOOP", path.to_string_lossy());
-    return 0;
+/// Name of an extended attribute, split into namespace and the name inside that namespace (e.g. `user.foo` is
+/// `(User, "foo")`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct XattrName {
+    namespace: XattrNamespace,
+    name: String,
 }
 
-pub unsafe extern "C" fn read<FS: Filesystem>(
-    path: *const i8,
-    buf: *mut i8,
-    size: usize,
-    offset: libc::off_t,
-    fuse_file_info: *mut libfuse::fuse_file_info,
-) -> i32 {
-    ensure_errno!(!path.is_null(), Errno::EINVAL);
-    ensure_errno!(!buf.is_null(), Errno::EINVAL);
-    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
-    ensure_errno!(path.is_aligned(), Errno::EINVAL);
-    ensure_errno!(buf.is_aligned(), Errno::EINVAL);
-    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+impl XattrName {
+    pub fn new(namespace: XattrNamespace, name: impl Into<String>) -> Result<Self, XattrNameError> {
+        let name = name.into();
+        if name.is_empty() {
+            return Err(XattrNameError::EmptyName);
+        }
+        if name.contains('\0') {
+            return Err(XattrNameError::InteriorNul(name));
+        }
+        Ok(Self { namespace, name })
+    }
 
-    let size: u32 = if size == 0 {
-        // nothing to do, no space left in buffer
-        return 0;
-    } else {
-        ensure_errno!(size <= i32::MAX as usize, Errno::EDOM);
-        size as u32
-    };
+    #[must_use]
+    pub fn namespace(&self) -> XattrNamespace {
+        self.namespace
+    }
 
-    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+    /// The name without its namespace prefix.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
 
-    // SAFETY: we check invariants at the function start
-    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+impl FromStr for XattrName {
+    type Err = XattrNameError;
 
-    debug!(
-        "enter: read('{}', buf=0x{buf:x}, size={size}, offset=0x{offset:x})",
-        path.to_string_lossy(),
-        buf = buf.addr()
-    );
-    let result = try_errno!(call_into_user_code::<FS, _>("read", || fs.read(
-        &path,
-        size,
-        offset as isize
-    )));
-    let n_bytes = result.content.len();
-    // if `n_bytes` is smaller than `size`, it _will_ fit inside i32. see checks at the top.
-    ensure_errno!(n_bytes <= size as usize, Errno::ENOSYS); // FIXME don't error if user code returns too much data. just truncate it.
-    let n_bytes = n_bytes as i32;
-    {
-        let content_as_string = String::from_utf8_lossy(
-            &result
-                .content
-                .get(0..50.min(result.content.len()))
-                .unwrap_or("<not found>".as_bytes()),
-        );
-        let char_count = content_as_string.chars().count();
-        debug!(
-            "return: read => ({n_bytes}):'{}'",
-            content_as_string + if char_count > 50 { "…" } else { "" }
-        );
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        XattrNamespace::ALL
+            .into_iter()
+            .find_map(|namespace| {
+                s.strip_prefix(namespace.prefix())
+                    .map(|name| Self::new(namespace, name))
+            })
+            .unwrap_or_else(|| Err(XattrNameError::UnknownNamespace(s.to_owned())))
     }
+}
 
-    // Safety: we checked that the buffer is big enough to hold the returned data (if `size` argument was correct).
-    //         Also we checked that the pointer is aligned and non-null.
-    unsafe {
-        ptr::copy_nonoverlapping(
-            result.content.as_ptr(),
-            buf as *mut u8,
-            result.content.len(),
-        );
+impl fmt::Display for XattrName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.namespace.prefix(), self.name)
     }
+}
 
-    return n_bytes;
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum XattrNameError {
+    #[error("Unknown xattr namespace in '{0}'")]
+    UnknownNamespace(String),
+    #[error("Empty xattr name")]
+    EmptyName,
+    #[error("Xattr name contains nul byte: '{0}'")]
+    InteriorNul(String),
 }
 
-fn fetch_fs_from_registry<FS: Filesystem>() -> Result<Arc<FS>, (String, Errno)> {
-    state::get::<FS>().map_err(|e| {
-        (
-            format!(
-                "State lookup for `{}` failed. Registry corrupted? ({e:#})",
-                std::any::type_name::<FS>()
-            ),
-            Errno::ENOTRECOVERABLE,
-        )
-    })
+impl From<XattrNameError> for Errno {
+    fn from(value: XattrNameError) -> Self {
+        match value {
+            // that's what the kernel returns for namespaces it doesn't know
+            XattrNameError::UnknownNamespace(_) => Errno::EOPNOTSUPP,
+            XattrNameError::EmptyName | XattrNameError::InteriorNul(_) => Errno::EINVAL,
+        }
+    }
 }
 
-fn call_into_user_code<FS: Filesystem, T>(
-    method: &str,
-    user_fn: impl FnOnce() -> Result<T, Errno>,
-) -> Result<T, (String, Errno)> {
-    let fs = std::any::type_name::<FS>();
-    std::panic::catch_unwind(core::panic::AssertUnwindSafe(user_fn))
-        .map_err(|panic| {
-            // abort, since internal state of filesystem impl can now be inconsistent
-            state::clear();
-            (
-                format!("PANIC on `{fs}::{method}`:\n\n{panic:?}\n"),
-                Errno::ENOTRECOVERABLE,
-            )
-        })
-        .and_then(|inner| inner.map_err(|e| (format!("Error in user code `{fs}::{method}`"), e)))
+/// The `flags` of `setxattr(2)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrSetMode {
+    /// Create the attribute, or replace its value if it exists.
+    Upsert,
+    /// Fail with `EEXIST` if the attribute already exists (`XATTR_CREATE`).
+    Create,
+    /// Fail with `ENODATA` if the attribute doesn't exist (`XATTR_REPLACE`).
+    Replace,
 }
 
-/// # Safety
-///
-/// - `c_str` - is a valid pointer (non-dangling, aligned), is nul-terminated
-///
-/// Since we copy the string instead of referencing it, aliasing and mutation of the original c string
-/// are not as important.
-unsafe fn path_from_c_ptr(c_str: *const c_char) -> Result<PathBuf, (String, Errno)> {
-    let path = unsafe { CStr::from_ptr(c_str) };
-    let path_utf8: &str = match path.to_str() {
-        Ok(path) => path,
-        Err(utf8_error) => {
-            return Err((
-                format!("path is not valid UTF-8: {utf8_error:#}"),
-                Errno::EINVAL,
-            ));
-        }
-    };
+impl TryFrom<i32> for XattrSetMode {
+    type Error = Errno;
 
-    // TODO (maybe) copy path because what lifetime should this &Path have? we can't depend it on a ptr.
-    let path_utf8 = PathBuf::from(path_utf8);
-    Ok(path_utf8)
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Upsert),
+            libc::XATTR_CREATE => Ok(Self::Create),
+            libc::XATTR_REPLACE => Ok(Self::Replace),
+            _ => Err(Errno::EINVAL),
+        }
+    }
 }
 
-/*fn call_with_catch_unwind<FS: Filesystem, T>(
-    fun: impl FnOnce() -> T,
-    method: &'static str,
-) -> Result<T, Errno> {
-    use core::panic::AssertUnwindSafe;
-    use std::panic::catch_unwind;
+// type GetattrHook = fn(
+//     path: *const i8,
+//     stat_out: *mut libfuse::stat,
+//     fuse_file_info_out: *mut libfuse::fuse_file_info,
+// ) -> i32;
+// type OpenHook = fn(path: *const c_char, fuse_file_info_out: *mut libfuse::fuse_file_info) -> i32;
+// type ReadHook = fn(
+//     path: *const c_char,
+//     buf_out: *mut c_char,
+//     n: size_t,
+//     offset: libfuse::off_t,
+//     fuse_file_info_out: *mut libfuse::fuse_file_info,
+// ) -> i32;
 
-    result.map_err(|e| {
-        error!(
-            "PANIC on `{fs}::{method}`:\n\n{e:?}\n\nreturning ENOSYS\n",
-            fs = std::any::type_name::<FS>()
-        );
-        Errno::ENOTRECOVERABLE
-    })
-}*/
+// ///
+// ///
+// type ReaddirHook = fn(
+//     path: *const c_char,
+//     data_ptr: *mut c_void,
+//     filler_fn: libfuse::fuse_fill_dir_t,
+//     offset: libfuse::off_t,
+//     fuse_file_info_out: *mut libfuse::fuse_file_info,
+//     readdir_flags: libfuse::fuse_readdir_flags,
+// ) -> i32;
 
-// ALTERNATIVE: store filesystem handles inside a vector, and use some fuse field to smuggle a vector offset
-//
+pub struct GetfattrRetVal {
+    pub stat: Stat,
+}
+
+pub struct StatfsRetVal {
+    pub stat_fs: StatFs,
+}
+
+/// What a directory listing knows about an entry besides its name.
+#[derive(Debug, Clone, Copy)]
+pub enum DirEntryAttributes {
+    /// The kernel has to `getattr` the entry to learn anything about it.
+    None,
+    /// Fills `d_type`, so e.g. `find -type d` doesn't need a `getattr` per entry.
+    FileType(FileType),
+    /// Complete attributes, which the kernel caches via readdirplus, sparing `ls -l` a `getattr` per entry.
+    Stat(Stat),
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: DirEntryName,
+    pub attributes: DirEntryAttributes,
+}
+
+impl DirEntry {
+    #[must_use]
+    pub fn with_file_type(name: DirEntryName, file_type: FileType) -> Self {
+        Self {
+            name,
+            attributes: DirEntryAttributes::FileType(file_type),
+        }
+    }
+
+    #[must_use]
+    pub fn with_stat(name: DirEntryName, stat: Stat) -> Self {
+        Self {
+            name,
+            attributes: DirEntryAttributes::Stat(stat),
+        }
+    }
+}
+
+impl From<DirEntryName> for DirEntry {
+    fn from(name: DirEntryName) -> Self {
+        Self {
+            name,
+            attributes: DirEntryAttributes::None,
+        }
+    }
+}
+
+/// A single path component, valid as the name of a directory entry: not empty, not `.` or `..`, no `/` or nul byte,
+/// and at most `NAME_MAX` bytes long.
+///
+/// `.` and `..` are added by the crate, see [`Filesystem::ADD_DOT_ENTRIES`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deref, Display)]
+pub struct DirEntryName(String);
+
+impl DirEntryName {
+    pub fn new(name: impl Into<String>) -> Result<Self, DirEntryNameError> {
+        let name = name.into();
+        if name.is_empty() {
+            return Err(DirEntryNameError::Empty);
+        }
+        if name == "." || name == ".." {
+            return Err(DirEntryNameError::Reserved(name));
+        }
+        if name.contains(['/', '\0']) {
+            return Err(DirEntryNameError::InvalidChar(name));
+        }
+        if name.len() > libc::NAME_MAX.unsigned_abs() as usize {
+            return Err(DirEntryNameError::TooLong(name));
+        }
+        Ok(Self(name))
+    }
+}
+
+impl FromStr for DirEntryName {
+    type Err = DirEntryNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl TryFrom<String> for DirEntryName {
+    type Error = DirEntryNameError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DirEntryNameError {
+    #[error("Empty dir entry name")]
+    Empty,
+    #[error("'{0}' is reserved, set `Filesystem::ADD_DOT_ENTRIES` instead")]
+    Reserved(String),
+    #[error("Dir entry name contains '/' or nul byte: '{0}'")]
+    InvalidChar(String),
+    #[error("Dir entry name is longer than NAME_MAX: '{0}'")]
+    TooLong(String),
+}
+
+impl From<DirEntryNameError> for Errno {
+    fn from(value: DirEntryNameError) -> Self {
+        match value {
+            DirEntryNameError::TooLong(_) => Errno::ENAMETOOLONG,
+            DirEntryNameError::Empty
+            | DirEntryNameError::Reserved(_)
+            | DirEntryNameError::InvalidChar(_) => Errno::EINVAL,
+        }
+    }
+}
+
+// TODO (maybe) use Cow and <T: AsRef<str>> params to let user choose wether to pass owned Strings or references.
+pub struct ReaddirRetVal {
+    pub entries: Vec<DirEntry>,
+    // seems to only be set from `open`, `opendir`, `create` - https://libfuse.github.io/doxygen/structfuse__file__info.html#afcff4109f1c8fb7ff51f18500496271d
+    //pub fuse_file_info: Option<FuseFileInfo>,
+}
+
+/// Whether [`DirFiller::add`] could add the entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillStatus {
+    Added,
+    /// The kernel's buffer is full, the entry wasn't added. Return from [`Filesystem::readdir_paged`], the kernel
+    /// will call it again with the offset of the first entry that didn't fit.
+    Full,
+}
+
+/// Sink for the entries of [`Filesystem::readdir_paged`], wraps libfuse's `fuse_fill_dir_t`.
+pub struct DirFiller<'a> {
+    data_ptr: *mut c_void,
+    filler_fn: unsafe extern "C" fn(
+        *mut c_void,
+        *const c_char,
+        *const libfuse::stat,
+        libfuse::off_t,
+        libfuse::fuse_fill_dir_flags,
+    ) -> i32,
+    full: bool,
+    /// Added to every user `next_offset`, to make room for the crate's own entries (`.` and `..`).
+    offset_shift: u64,
+    _buffer: std::marker::PhantomData<&'a mut c_void>,
+}
+
+impl DirFiller<'_> {
+    /// Add `entry`.
+    ///
+    /// `next_offset` is the cookie of the entry *after* this one: if the listing is interrupted, the kernel calls
+    /// [`Filesystem::readdir_paged`] again with it to continue. It must be non-zero and stable across calls (e.g. an
+    /// index, or a hash of the name).
+    pub fn add(&mut self, entry: &DirEntry, next_offset: u64) -> Result<FillStatus, Errno> {
+        let DirEntry { name, attributes } = entry;
+        if next_offset == 0 {
+            error!("readdir offset of '{name}' must not be 0, that's the start of the listing");
+            return Err(Errno::EINVAL);
+        }
+        let Some(next_offset) = next_offset.checked_add(self.offset_shift) else {
+            error!("readdir offset {next_offset} of '{name}' doesn't fit `off_t`");
+            return Err(Errno::EINVAL);
+        };
+        self.fill(name, *attributes, next_offset)
+    }
+
+    /// Adds `.` and `..` with the offsets 1 and 2, if `offset` says they are still missing, and shifts all following
+    /// offsets past them. Returns the offset to pass on to the user.
+    fn add_dot_entries(&mut self, offset: u64) -> Result<u64, Errno> {
+        let directory = DirEntryAttributes::FileType(FileType::Directory);
+        for (name, next_offset) in [(".", 1), ("..", 2)] {
+            if offset < next_offset {
+                self.fill(name, directory, next_offset)?;
+            }
+        }
+        self.offset_shift = 2;
+        Ok(offset.saturating_sub(2))
+    }
+
+    /// `name` must be a valid entry name, which isn't checked here to allow `.` and `..`.
+    fn fill(
+        &mut self,
+        name: &str,
+        attributes: DirEntryAttributes,
+        next_offset: u64,
+    ) -> Result<FillStatus, Errno> {
+        if self.full {
+            return Ok(FillStatus::Full);
+        }
+        let Ok(next_offset) = libfuse::off_t::try_from(next_offset) else {
+            error!("readdir offset {next_offset} of '{name}' doesn't fit `off_t`");
+            return Err(Errno::EINVAL);
+        };
+        let Ok(name_as_c_string) = CString::new(name) else {
+            error!("dir entry '{name}' contains a nul byte");
+            return Err(Errno::EIO);
+        };
+
+        let (stat, fill_flags) = match attributes {
+            // setting `stat` struct to NULL, as per `hello.c`
+            DirEntryAttributes::None => (None, libfuse::fuse_fill_dir_flags_FUSE_FILL_DIR_DEFAULTS),
+            DirEntryAttributes::FileType(file_type) => {
+                // SAFETY: `stat` is plain old data, all-zero is a valid value
+                let mut stat: libfuse::stat = unsafe { std::mem::zeroed() };
+                // without `…_PLUS`, libfuse only looks at the file type bits (and `st_ino` with `-o use_ino`)
+                stat.st_mode = file_type as FileModeRepr;
+                (
+                    Some(stat),
+                    libfuse::fuse_fill_dir_flags_FUSE_FILL_DIR_DEFAULTS,
+                )
+            }
+            // …_PLUS => let kernel fill inode cache by announcing that the stat param is fully set
+            DirEntryAttributes::Stat(stat) => {
+                (Some(*stat), libfuse::fuse_fill_dir_flags_FUSE_FILL_DIR_PLUS)
+            }
+        };
+
+        // SAFETY: `data_ptr` and `filler_fn` come straight from libfuse, which keeps them valid during `readdir`,
+        // which outlives `'a`. `stat` is only borrowed for the call.
+        let fill_result = unsafe {
+            (self.filler_fn)(
+                self.data_ptr,
+                name_as_c_string.as_ptr(),
+                stat.as_ref().map_or(ptr::null(), ptr::from_ref),
+                next_offset,
+                fill_flags,
+            )
+        };
+        if fill_result != 0 {
+            self.full = true;
+            return Ok(FillStatus::Full);
+        }
+        Ok(FillStatus::Added)
+    }
+}
+
+/// libfuse's reply buffer for [`Filesystem::read_into`]. It starts out uninitialized, so it is filled from the
+/// front, and only the filled part is readable. Everything filled is sent as reply.
+pub struct ReadBuf<'a> {
+    buf: &'a mut [MaybeUninit<u8>],
+    filled: usize,
+}
+
+impl<'a> ReadBuf<'a> {
+    fn new(buf: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self { buf, filled: 0 }
+    }
+
+    /// The requested size.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Bytes that can still be filled.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.filled
+    }
+
+    #[must_use]
+    pub fn filled(&self) -> &[u8] {
+        // SAFETY: the first `filled` bytes were initialized by `put_slice` or `advance`
+        unsafe { std::slice::from_raw_parts(self.buf.as_ptr().cast::<u8>(), self.filled) }
+    }
+
+    /// Copies as much of `data` as fits behind the filled part, and returns how much that was.
+    pub fn put_slice(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.remaining());
+        self.buf[self.filled..self.filled + n].write_copy_of_slice(&data[..n]);
+        self.filled += n;
+        n
+    }
+
+    /// The part behind the filled part, to be written to directly (e.g. by `pread(2)`), see [`ReadBuf::advance`].
+    pub fn unfilled(&mut self) -> &mut [MaybeUninit<u8>] {
+        &mut self.buf[self.filled..]
+    }
+
+    /// Marks the first `n` bytes of [`ReadBuf::unfilled`] as filled.
+    ///
+    /// # Panics
+    ///
+    /// If `n` exceeds [`ReadBuf::remaining`].
+    ///
+    /// # Safety
+    ///
+    /// - the first `n` bytes of [`ReadBuf::unfilled`] have been initialized
+    pub unsafe fn advance(&mut self, n: usize) {
+        assert!(
+            n <= self.remaining(),
+            "advancing {n} bytes, but only {} remain",
+            self.remaining()
+        );
+        self.filled += n;
+    }
+}
+
+pub struct OpenRetVal<FH> {
+    /// Handed back to every operation on this open file, and dropped on `release`. See [`Filesystem::FileHandle`].
+    pub file_handle: FH,
+    /// `None` keeps libfuse's defaults (which may come from mount options like `-o direct_io`).
+    pub fuse_file_info: Option<FuseFileInfo>,
+}
+
+pub struct ReadRetVal {
+    pub content: Vec<u8>,
+}
+
+pub struct ReadlinkRetVal {
+    /// Truncated to the buffer size libfuse provides, as `readlink(2)` does.
+    pub target: PathBuf,
+}
+
+pub struct GetxattrRetVal {
+    pub value: Vec<u8>,
+}
+
+pub struct ListxattrRetVal {
+    pub names: Vec<XattrName>,
+}
+
+pub struct WriteRetVal {
+    /// Must not exceed the length of the data passed to [`Filesystem::write`].
+    pub n_written: usize,
+}
+
+/// A feature of the kernel connection, negotiated in [`Filesystem::init`], see
+/// <https://libfuse.github.io/doxygen/fuse__common_8h.html>.
+///
+/// There is no flag for big writes, they are always on in FUSE 3. Use [`ConnectionInfo::max_write`] instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Capability {
+    AsyncRead = libfuse::FUSE_CAP_ASYNC_READ,
+    PosixLocks = libfuse::FUSE_CAP_POSIX_LOCKS,
+    AtomicOTrunc = libfuse::FUSE_CAP_ATOMIC_O_TRUNC,
+    ExportSupport = libfuse::FUSE_CAP_EXPORT_SUPPORT,
+    DontMask = libfuse::FUSE_CAP_DONT_MASK,
+    SpliceWrite = libfuse::FUSE_CAP_SPLICE_WRITE,
+    SpliceMove = libfuse::FUSE_CAP_SPLICE_MOVE,
+    SpliceRead = libfuse::FUSE_CAP_SPLICE_READ,
+    FlockLocks = libfuse::FUSE_CAP_FLOCK_LOCKS,
+    IoctlDir = libfuse::FUSE_CAP_IOCTL_DIR,
+    AutoInvalData = libfuse::FUSE_CAP_AUTO_INVAL_DATA,
+    Readdirplus = libfuse::FUSE_CAP_READDIRPLUS,
+    ReaddirplusAuto = libfuse::FUSE_CAP_READDIRPLUS_AUTO,
+    AsyncDio = libfuse::FUSE_CAP_ASYNC_DIO,
+    WritebackCache = libfuse::FUSE_CAP_WRITEBACK_CACHE,
+    /// [`Filesystem::open`] may fail with `ENOSYS`, and the kernel won't call it again. Operations on such files get
+    /// no [`Filesystem::FileHandle`], and aren't released.
+    NoOpenSupport = libfuse::FUSE_CAP_NO_OPEN_SUPPORT,
+    ParallelDirops = libfuse::FUSE_CAP_PARALLEL_DIROPS,
+    PosixAcl = libfuse::FUSE_CAP_POSIX_ACL,
+    HandleKillpriv = libfuse::FUSE_CAP_HANDLE_KILLPRIV,
+    CacheSymlinks = libfuse::FUSE_CAP_CACHE_SYMLINKS,
+    /// [`Filesystem::opendir`] may fail with `ENOSYS`, and the kernel won't call it again.
+    NoOpendirSupport = libfuse::FUSE_CAP_NO_OPENDIR_SUPPORT,
+    ExplicitInvalData = libfuse::FUSE_CAP_EXPLICIT_INVAL_DATA,
+    ExpireOnly = libfuse::FUSE_CAP_EXPIRE_ONLY,
+    SetxattrExt = libfuse::FUSE_CAP_SETXATTR_EXT,
+    DirectIoAllowMmap = libfuse::FUSE_CAP_DIRECT_IO_ALLOW_MMAP,
+    Passthrough = libfuse::FUSE_CAP_PASSTHROUGH,
+    NoExportSupport = libfuse::FUSE_CAP_NO_EXPORT_SUPPORT,
+}
+
+impl Capability {
+    pub const ALL: [Self; 27] = [
+        Self::AsyncRead,
+        Self::PosixLocks,
+        Self::AtomicOTrunc,
+        Self::ExportSupport,
+        Self::DontMask,
+        Self::SpliceWrite,
+        Self::SpliceMove,
+        Self::SpliceRead,
+        Self::FlockLocks,
+        Self::IoctlDir,
+        Self::AutoInvalData,
+        Self::Readdirplus,
+        Self::ReaddirplusAuto,
+        Self::AsyncDio,
+        Self::WritebackCache,
+        Self::NoOpenSupport,
+        Self::ParallelDirops,
+        Self::PosixAcl,
+        Self::HandleKillpriv,
+        Self::CacheSymlinks,
+        Self::NoOpendirSupport,
+        Self::ExplicitInvalData,
+        Self::ExpireOnly,
+        Self::SetxattrExt,
+        Self::DirectIoAllowMmap,
+        Self::Passthrough,
+        Self::NoExportSupport,
+    ];
+
+    fn bit(self) -> u64 {
+        u64::from(self as u32)
+    }
+}
+
+/// A set of [`Capability`]. Bits unknown to this crate are kept as they are.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Into)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    #[must_use]
+    pub fn contains(self, capability: Capability) -> bool {
+        self.0 & capability.bit() != 0
+    }
+
+    pub fn insert(&mut self, capability: Capability) {
+        self.0 |= capability.bit();
+    }
+
+    pub fn remove(&mut self, capability: Capability) {
+        self.0 &= !capability.bit();
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Capability> {
+        Capability::ALL
+            .into_iter()
+            .filter(move |capability| self.contains(*capability))
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// The kernel connection, as negotiated in [`Filesystem::init`], see
+/// <https://libfuse.github.io/doxygen/structfuse__conn__info.html>.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    protocol_version: (u32, u32),
+    capable: Capabilities,
+    /// Capabilities to enable, libfuse already enables some by default, including [`Capability::Readdirplus`] (see
+    /// [`DirEntryAttributes::Stat`]) and [`Capability::ReaddirplusAuto`] if possible.
+    ///
+    /// Capabilities the kernel isn't capable of are dropped with a warning after `init`.
+    pub want: Capabilities,
+    /// Upper bound of bytes per write request.
+    pub max_write: u32,
+    /// Upper bound of bytes per read request, only settable with the `max_read` mount option.
+    pub max_read: u32,
+    /// Upper bound of bytes the kernel reads ahead.
+    pub max_readahead: u32,
+    /// Upper bound of pending background requests (e.g. readahead, async reads).
+    pub max_background: u32,
+    /// Number of pending background requests, from which on the kernel considers the file system congested.
+    pub congestion_threshold: u32,
+    /// Granularity of the timestamps the file system supports, in nanoseconds. 0 is the same as 1.
+    pub time_gran: u32,
+}
+
+impl ConnectionInfo {
+    fn new(conn: &libfuse::fuse_conn_info) -> Self {
+        Self {
+            protocol_version: (conn.proto_major, conn.proto_minor),
+            capable: Capabilities(conn.capable_ext),
+            want: Capabilities(conn.want_ext),
+            max_write: conn.max_write,
+            max_read: conn.max_read,
+            max_readahead: conn.max_readahead,
+            max_background: conn.max_background,
+            congestion_threshold: conn.congestion_threshold,
+            time_gran: conn.time_gran,
+        }
+    }
+
+    /// FUSE protocol version as `(major, minor)`.
+    #[must_use]
+    pub fn protocol_version(&self) -> (u32, u32) {
+        self.protocol_version
+    }
+
+    /// Capabilities supported by the kernel and libfuse.
+    #[must_use]
+    pub fn capable(&self) -> Capabilities {
+        self.capable
+    }
+
+    /// Enables `capability` if the kernel supports it, and returns whether it does.
+    pub fn want_if_capable(&mut self, capability: Capability) -> bool {
+        let capable = self.capable.contains(capability);
+        if capable {
+            self.want.insert(capability);
+        }
+        capable
+    }
+
+    fn apply_to(self, conn: &mut libfuse::fuse_conn_info) {
+        let Self {
+            protocol_version: _,
+            capable,
+            want,
+            max_write,
+            max_read,
+            max_readahead,
+            max_background,
+            congestion_threshold,
+            time_gran,
+        } = self;
+        let unsupported = want.0 & !capable.0;
+        if unsupported != 0 {
+            warn!(
+                "init: dropping wanted capabilities the kernel isn't capable of: {:?}",
+                Capabilities(unsupported)
+            );
+        }
+        conn.want_ext = want.0 & capable.0;
+        conn.max_write = max_write;
+        conn.max_read = max_read;
+        conn.max_readahead = max_readahead;
+        conn.max_background = max_background;
+        conn.congestion_threshold = congestion_threshold;
+        conn.time_gran = time_gran;
+    }
+}
+
+/// Settings of libfuse's high-level layer, initialized from the mount options (see [`fuse_main`]) and adjustable
+/// in [`Filesystem::init`]. See <https://libfuse.github.io/doxygen/structfuse__config.html>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FuseConfig {
+    /// Report `st_ino` from [`Filesystem::getattr`] instead of libfuse's own inode numbers.
+    pub use_ino: bool,
+    /// Like `use_ino`, but for directory entries without a [`Stat`], by looking them up.
+    pub readdir_ino: bool,
+    /// Open every file with `direct_io`, see [`FuseFileInfo::direct_io`].
+    pub direct_io: bool,
+    /// Open every file with `keep_cache`, see [`FuseFileInfo::keep_cache`].
+    pub kernel_cache: bool,
+    /// Invalidate cached content when the modification time or size change.
+    pub auto_cache: bool,
+    /// Remove unlinked files right away instead of hiding them while they are open.
+    pub hard_remove: bool,
+    /// How long the kernel caches name lookups.
+    pub entry_timeout: Duration,
+    /// How long the kernel caches failed name lookups.
+    pub negative_timeout: Duration,
+    /// How long the kernel caches attributes.
+    pub attr_timeout: Duration,
+}
+
+impl FuseConfig {
+    fn new(cfg: &libfuse::fuse_config) -> Self {
+        fn timeout(name: &str, secs: f64) -> Duration {
+            Duration::try_from_secs_f64(secs).unwrap_or_else(|e| {
+                warn!("init: `{name}` of {secs}s is invalid, using 0 ({e:#})");
+                Duration::ZERO
+            })
+        }
+
+        Self {
+            use_ino: cfg.use_ino != 0,
+            readdir_ino: cfg.readdir_ino != 0,
+            direct_io: cfg.direct_io != 0,
+            kernel_cache: cfg.kernel_cache != 0,
+            auto_cache: cfg.auto_cache != 0,
+            hard_remove: cfg.hard_remove != 0,
+            entry_timeout: timeout("entry_timeout", cfg.entry_timeout),
+            negative_timeout: timeout("negative_timeout", cfg.negative_timeout),
+            attr_timeout: timeout("attr_timeout", cfg.attr_timeout),
+        }
+    }
+
+    fn apply_to(self, cfg: &mut libfuse::fuse_config) {
+        let Self {
+            use_ino,
+            readdir_ino,
+            direct_io,
+            kernel_cache,
+            auto_cache,
+            hard_remove,
+            entry_timeout,
+            negative_timeout,
+            attr_timeout,
+        } = self;
+        cfg.use_ino = use_ino.into();
+        cfg.readdir_ino = readdir_ino.into();
+        cfg.direct_io = direct_io.into();
+        cfg.kernel_cache = kernel_cache.into();
+        cfg.auto_cache = auto_cache.into();
+        cfg.hard_remove = hard_remove.into();
+        // the callbacks need a path, even for open files (`-o nullpath_ok` would pass NULL)
+        cfg.nullpath_ok = 0;
+        cfg.entry_timeout = entry_timeout.as_secs_f64();
+        cfg.negative_timeout = negative_timeout.as_secs_f64();
+        cfg.attr_timeout = attr_timeout.as_secs_f64();
+    }
+}
+
+pub trait Filesystem: Send + Sync + 'static {
+    /// Per-open state, created by [`Filesystem::open`] (or [`Filesystem::create`]) and passed to every following
+    /// operation on the same open file. The crate owns it in between, and drops it on `release`.
+    ///
+    /// Use `()` for stateless file I/O.
+    ///
+    /// Operations get `None` instead if the kernel skipped `open`, see [`Capability::NoOpenSupport`].
+    type FileHandle: Send + Sync + 'static;
+
+    /// Let the crate list `.` and `..` (as directories) in front of the entries of every directory, as POSIX
+    /// requires. Their names are rejected by [`DirEntryName`], so this is the only way to list them.
+    const ADD_DOT_ENTRIES: bool = false;
+
+    // connection lifecycle
+
+    /// Called once the kernel connection is established, before any other operation. Inspect what the kernel
+    /// supports in `conn`, and enable features with [`ConnectionInfo::want`].
+    fn init(&self, _conn: &mut ConnectionInfo, _cfg: &mut FuseConfig) {}
+    /// Called once on unmount, after the last operation.
+    fn destroy(&self) {}
+
+    /// Defaults to `ENOSYS`, so a useful file system implements this.
+    fn getattr(&self, _path: &Path) -> Result<GetfattrRetVal, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// List the whole directory at once. Implement [`Filesystem::readdir_paged`] instead for large directories.
+    fn readdir(&self, _path: &Path) -> Result<ReaddirRetVal, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Stream the directory's entries into `filler`, starting after the entry whose `next_offset` is `offset` (0 is
+    /// the start). Stop once `filler` reports [`FillStatus::Full`].
+    ///
+    /// The default lists the whole directory with [`Filesystem::readdir`] on every call, and uses the entry index as
+    /// offset.
+    fn readdir_paged(
+        &self,
+        path: &Path,
+        offset: u64,
+        filler: &mut DirFiller<'_>,
+    ) -> Result<(), Errno> {
+        let ReaddirRetVal { entries } = self.readdir(path)?;
+        let skip = usize::try_from(offset).unwrap_or(usize::MAX);
+        for (index, entry) in entries.iter().enumerate().skip(skip) {
+            if filler.add(entry, index as u64 + 1)? == FillStatus::Full {
+                break;
+            }
+        }
+        Ok(())
+    }
+    /// Defaults to `ENOSYS`, which fails every `open(2)` unless [`Capability::NoOpenSupport`] is wanted.
+    fn open(&self, _path: &Path, _flags: OpenFlags) -> Result<OpenRetVal<Self::FileHandle>, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Read up to `size` bytes at `offset`. Implement [`Filesystem::read_into`] instead to avoid the allocation.
+    ///
+    /// Content beyond `size` bytes is cut off (with a warning).
+    fn read(
+        &self,
+        _path: &Path,
+        _file_handle: Option<&Self::FileHandle>,
+        _size: ReadSize,
+        _offset: u64,
+    ) -> Result<ReadRetVal, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Read into `buf`, which is libfuse's reply buffer, starting at `offset`. Whatever is filled into `buf` is the
+    /// reply.
+    ///
+    /// The default calls [`Filesystem::read`] and copies its result.
+    fn read_into(
+        &self,
+        path: &Path,
+        file_handle: Option<&Self::FileHandle>,
+        buf: &mut ReadBuf<'_>,
+        offset: u64,
+    ) -> Result<(), Errno> {
+        let Ok(size) = ReadSize::new(buf.capacity()) else {
+            error!("read buffer of {} bytes out of range", buf.capacity());
+            return Err(Errno::EINVAL);
+        };
+        let ReadRetVal { content } = self.read(path, file_handle, size, offset)?;
+        if buf.put_slice(&content) < content.len() {
+            warn!(
+                "`read` returned {} bytes, but only {size} were requested. Truncating.",
+                content.len()
+            );
+        }
+        Ok(())
+    }
+
+    // open file lifecycle
+
+    /// Called on every `close(2)` of a file descriptor, so possibly several times per open file (`dup(2)`,
+    /// `fork(2)`). Report deferred write errors here, since `close(2)` can still return them to the caller.
+    fn flush(&self, _path: &Path, _file_handle: Option<&Self::FileHandle>) -> Result<(), Errno> {
+        Ok(())
+    }
+    /// Called exactly once per open file, after the last file descriptor was closed. The error is not reported to
+    /// any caller. `file_handle` is dropped right after this returns, even on error.
+    fn release(&self, _path: &Path, _file_handle: &Self::FileHandle) -> Result<(), Errno> {
+        Ok(())
+    }
+    /// Persist the file's contents. If `datasync` is set, only the data has to be persisted, not the metadata.
+    ///
+    /// The default returns `ENOSYS`, which the kernel treats as success, and stops sending `fsync`.
+    fn fsync(
+        &self,
+        _path: &Path,
+        _file_handle: Option<&Self::FileHandle>,
+        _datasync: bool,
+    ) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Check whether the directory may be listed. Called before `readdir`.
+    fn opendir(&self, _path: &Path) -> Result<(), Errno> {
+        Ok(())
+    }
+    /// Counterpart to `opendir`, called once the directory isn't listed anymore.
+    fn releasedir(&self, _path: &Path) -> Result<(), Errno> {
+        Ok(())
+    }
+    /// Like [`Filesystem::fsync`], but for directories. Defaults to `ENOSYS` as well.
+    fn fsyncdir(&self, _path: &Path, _datasync: bool) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+
+    // write support. Everything below defaults to `ENOSYS`, so read-only filesystems don't have to care.
+
+    /// Write `data` to the file at `offset`. A short write (`n_written < data.len()`) is reported as such to the
+    /// kernel, which usually turns it into an error for the caller.
+    fn write(
+        &self,
+        _path: &Path,
+        _file_handle: Option<&Self::FileHandle>,
+        _data: &[u8],
+        _offset: u64,
+    ) -> Result<WriteRetVal, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Create and open a file. If this returns `ENOSYS`, the kernel falls back to `mknod` + `open`.
+    fn create(
+        &self,
+        _path: &Path,
+        _mode: FileMode,
+        _flags: OpenFlags,
+    ) -> Result<OpenRetVal<Self::FileHandle>, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Change the size of a file. `size` is guaranteed to be non-negative.
+    fn truncate(&self, _path: &Path, _size: u64) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Remove a file (not a directory).
+    fn unlink(&self, _path: &Path) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+
+    // metadata
+
+    /// Change the permission bits of a file. The file type of `mode` matches the one reported by `getattr`.
+    fn chmod(&self, _path: &Path, _mode: FileMode) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Change owner and/or group of a file. `None` means the respective id should stay unchanged.
+    fn chown(&self, _path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Change the access and modification time of a file, e.g. on `touch`.
+    fn utimens(&self, _path: &Path, _atime: UtimeSpec, _mtime: UtimeSpec) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+
+    /// Statistics of the whole file system, `path` is any path inside it.
+    ///
+    /// The default reports [`StatFs::unknown`].
+    fn statfs(&self, _path: &Path) -> Result<StatfsRetVal, Errno> {
+        Ok(StatfsRetVal {
+            stat_fs: StatFs::unknown(),
+        })
+    }
+
+    // extended attributes. The size-probing of `getxattr(2)`/`listxattr(2)` is handled by the crate.
+
+    /// Return the value of the attribute `name`, or `ENODATA` if it doesn't exist.
+    fn getxattr(&self, _path: &Path, _name: &XattrName) -> Result<GetxattrRetVal, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    fn setxattr(
+        &self,
+        _path: &Path,
+        _name: &XattrName,
+        _value: &[u8],
+        _mode: XattrSetMode,
+    ) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// List the names of all attributes of `path`.
+    fn listxattr(&self, _path: &Path) -> Result<ListxattrRetVal, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Remove the attribute `name`, or return `ENODATA` if it doesn't exist.
+    fn removexattr(&self, _path: &Path, _name: &XattrName) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+
+    // links
+
+    /// Return the target of the symbolic link at `path`.
+    fn readlink(&self, _path: &Path) -> Result<ReadlinkRetVal, Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Create a symbolic link at `link_path`, pointing to `target`. `target` is stored verbatim, it doesn't have to
+    /// exist or even be inside this filesystem.
+    fn symlink(&self, _target: &Path, _link_path: &Path) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Create a hard link `to` that refers to the same file as `from`.
+    fn link(&self, _from: &Path, _to: &Path) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+
+    // directory mutation
+
+    /// Create a directory. `mode` always has [`FileType::Directory`] set, the kernel only passes the permission bits.
+    fn mkdir(&self, _path: &Path, _mode: FileMode) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Remove an (empty) directory.
+    fn rmdir(&self, _path: &Path) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+    /// Rename `from` to `to`, see [`RenameFlags`] for the semantics of `flags`.
+    fn rename(&self, _from: &Path, _to: &Path, _flags: RenameFlags) -> Result<(), Errno> {
+        Err(Errno::ENOSYS)
+    }
+}
+
+/*unsafe extern "C" {
+impl<FS: Filesystem> FSImplForC<FS> {
+    pub fn getfattr(path: &Path, stat: &mut Stat, fuse_file_info: &mut FuseFileInfo) -> i32 {
+        let fs = match state::get::<FS>() {
+            Ok(fs) => fs,
+            Err(e) => {
+                error!("registry error on `{}`: {e:#}", type_name::<FS>());
+                return -EFAULT;
+            }
+        };
+
+        return -(fs.getattr(path) as i32);
+    }
+    Option<
+}
+    }
+*/
+
+// INVALID cannot coerce closure to c-style call? even if we could, there would be no state captured,
+// so we're back to square one (just using static trampoline functions)
+// #[allow(non_snake_case)]
+// pub fn passthrough_getattr(fs: impl Filesystem) -> GettattrFuseFn {
+//     |_, _, _| { /* fs.getattr(…) */ }
+// }
+
+// OUTLOOK pass global static pointer to `Filesystem` struct, instead of
+// looking this up in a registry. Pro: multiple filesystem per impl allowed.
+// Cons: since rust doesn't allow pointers as const generic, we have to (unsafely)
+// cast between usize and pointer.
+// has libfuse compatible signature, can be passed inside `fuse_operations`
+
+///
+pub unsafe extern "C" fn getattr<FS: Filesystem>(
+    path: *const i8,
+    stat_out: *mut libfuse::stat,
+    _fuse_file_info_out: *mut libfuse::fuse_file_info,
+) -> i32 {
+    // Safety
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!stat_out.is_null(), Errno::EINVAL);
+    //   ensure_errno!(!_fuse_file_info_out.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(stat_out.is_aligned(), Errno::EINVAL);
+    //    ensure_errno!(_fuse_file_info_out.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // THESIS https://doc.rust-lang.org/edition-guide/rust-2024/unsafe-op-in-unsafe-fn.html
+    // safe wrapping of params
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: getfattr('{}')", path.to_string_lossy());
+    let result = try_errno!(call_into_user_code::<FS, _>("getfattr", || fs.getattr(&path)));
+    let GetfattrRetVal { stat } = result;
+    debug!("return: getfattr => {}", path.to_string_lossy());
+
+    // SAFETY: we assume that the two outptrs received by libfuse are not dangling. We can check for alignment and
+    // non-null-ity, but invalid memory addresses will not be caught.
+    unsafe {
+        *stat_out = *stat;
+    }
+
+    return 0;
+}
+
+/// * `data_ptr` - the kernel's buffer, opaque to us, passed on to `filler_fn`
+/// * `filler_fn` - called once per directory entry, returns non-zero once the buffer is full
+/// * `offset` - 0, or the `next_offset` of the last entry that was added by the previous call
+#[tracing::instrument]
+pub unsafe extern "C" fn readdir<FS: Filesystem>(
+    path: *const c_char,
+    data_ptr: *mut c_void,
+    filler_fn: libfuse::fuse_fill_dir_t,
+    offset: libfuse::off_t,
+    fuse_file_info_out: *mut libfuse::fuse_file_info,
+    _readdir_flags: libfuse::fuse_readdir_flags,
+) -> i32 {
+    let Some(filler_fn) = filler_fn else {
+        bail_errno!("`filler_fn` must not be null", Errno::EINVAL);
+    };
+
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!fuse_file_info_out.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(fuse_file_info_out.is_aligned(), Errno::EINVAL);
+
+    // since we don't use `data_ptr` besides passing it to `filler_fn`, we can ignore invariants.
+    //ensure_errno!(data_ptr.is_aligned(), Errno::EINVAL);
+    //ensure_errno!(!data_ptr.is_null(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    let offset = try_errno!(
+        u64::try_from(offset)
+            .map_err(|e| (format!("negative readdir offset: {e:#}"), Errno::EINVAL))
+    );
+    let mut filler = DirFiller {
+        data_ptr,
+        filler_fn,
+        full: false,
+        offset_shift: 0,
+        _buffer: std::marker::PhantomData,
+    };
+    let offset = if FS::ADD_DOT_ENTRIES {
+        try_errno!(
+            filler
+                .add_dot_entries(offset)
+                .map_err(|e| ("adding `.` and `..`".to_owned(), e))
+        )
+    } else {
+        offset
+    };
+
+    debug!(
+        "enter: readdir('{}', offset={offset})",
+        path.to_string_lossy()
+    );
+    try_errno!(
+        call_into_user_code::<FS, _>("readdir", || fs.readdir_paged(&path, offset, &mut filler))
+    );
+    debug!("return: readdir => full={}", filler.full);
+
+    return 0;
+}
+
+/// FUSE docs:
+///
+/// ```quote
+///
+/// Open a file
+///
+/// Open flags are available in fi->flags. The following rules apply.
+///
+/// - Creation (O_CREAT, O_EXCL, O_NOCTTY) flags will be filtered out / handled by the kernel.
+/// - Access modes (O_RDONLY, O_WRONLY, O_RDWR, O_EXEC, O_SEARCH) should be used by the filesystem to check if the operation is permitted. If the -o default_permissions mount option is given, this check is already done by the kernel before calling open() and may thus be omitted by the filesystem.
+/// - When writeback caching is enabled, the kernel may send read requests even for files opened with O_WRONLY. The filesystem should be prepared to handle this.
+/// - When writeback caching is disabled, the filesystem is expected to properly handle the O_APPEND flag and ensure that each write is appending to the end of the file.
+/// - When writeback caching is enabled, the kernel will handle O_APPEND. However, unless all changes to the file come through the kernel this will not work reliably. The filesystem should thus either ignore the O_APPEND flag (and let the kernel handle it), or return an error (indicating that reliably O_APPEND is not available).
+///
+/// Filesystem may store an arbitrary file handle (pointer, index, etc) in fi->fh, and use this in other all other file operations (read, write, flush, release, fsync).
+///
+/// Filesystem may also implement stateless file I/O and not store anything in fi->fh.
+///
+/// There are also some flags (direct_io, keep_cache) which the filesystem may set in fi, to change the way the file is opened. See fuse_file_info structure in <fuse_common.h> for more details.
+///
+/// If this request is answered with an error code of ENOSYS and FUSE_CAP_NO_OPEN_SUPPORT is set in fuse_conn_info.capable, this is treated as success and future calls to open will also succeed without being sent to the filesystem process.
+///
+/// Definition at line 486 of file fuse.h.
+///
+/// ```
+#[instrument]
+pub unsafe extern "C" fn open<FS: Filesystem>(
+    path: *const i8,
+    fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+
+    // SAFETY: we check invariants at the function start
+    let flags = unsafe { OpenFlags((*fuse_file_info).flags) };
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: open('{}', {flags:?})", path.to_string_lossy());
+    let OpenRetVal {
+        file_handle,
+        fuse_file_info: options,
+    } = try_errno!(call_into_user_code::<FS, _>("open", || fs.open(&path, flags)));
+    debug!("return: open => {options:?}");
+
+    // SAFETY: we check invariants at the function start
+    let fuse_file_info = unsafe { &mut *fuse_file_info };
+    if let Some(options) = options {
+        options.apply_to(fuse_file_info);
+    }
+    store_file_handle(fuse_file_info, file_handle);
+
+    return 0;
+}
+
+pub unsafe extern "C" fn read<FS: Filesystem>(
+    path: *const i8,
+    buf: *mut i8,
+    size: usize,
+    offset: libc::off_t,
+    fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!buf.is_null(), Errno::EINVAL);
+    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(buf.is_aligned(), Errno::EINVAL);
+    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+
+    if size == 0 {
+        // nothing to do, no space left in buffer
+        return 0;
+    }
+    let size = try_errno!(ReadSize::new(size).map_err(|e| (format!("{e:#}"), Errno::EDOM)));
+    let offset = try_errno!(
+        u64::try_from(offset).map_err(|e| (format!("negative read offset: {e:#}"), Errno::EINVAL))
+    );
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+    // SAFETY: we check invariants at the function start, and only we write `fh`. Not set if `open` was skipped,
+    // see `Capability::NoOpenSupport`.
+    let file_handle = unsafe { borrow_file_handle::<FS::FileHandle>(&*fuse_file_info) };
+
+    debug!(
+        "enter: read('{}', buf=0x{buf:x}, size={size}, offset=0x{offset:x})",
+        path.to_string_lossy(),
+        buf = buf.addr()
+    );
+    // SAFETY: we checked that the pointer is aligned and non-null, libfuse guarantees `size` bytes behind it, and
+    // `size <= i32::MAX`. The bytes may be uninitialized, which `MaybeUninit` allows.
+    let mut buf = ReadBuf::new(unsafe {
+        std::slice::from_raw_parts_mut(buf.cast::<MaybeUninit<u8>>(), size.get())
+    });
+    try_errno!(call_into_user_code::<FS, _>("read", || fs.read_into(
+        &path,
+        file_handle,
+        &mut buf,
+        offset
+    )));
+    let n_read = buf.filled().len();
+    // `n_read <= size`, so it _will_ fit inside i32. see checks at the top.
+    let n_bytes = n_read as i32;
+    {
+        let content_as_string = String::from_utf8_lossy(&buf.filled()[..n_read.min(50)]);
+        let char_count = content_as_string.chars().count();
+        debug!(
+            "return: read => ({n_bytes}):'{}'",
+            content_as_string + if char_count > 50 { "…" } else { "" }
+        );
+    }
+
+    return n_bytes;
+}
+
+pub unsafe extern "C" fn chmod<FS: Filesystem>(
+    path: *const i8,
+    mode: libfuse::mode_t,
+    // may be NULL if not issued on an open file
+    _fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    let mode = try_errno!(FileMode::try_from(mode).map_err(|e| (format!("{e:#}"), Errno::EINVAL)));
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!(
+        "enter: chmod('{}', mode=0o{:o})",
+        path.to_string_lossy(),
+        mode.0
+    );
+    try_errno!(call_into_user_code::<FS, _>("chmod", || fs.chmod(&path, mode)));
+    debug!("return: chmod => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn chown<FS: Filesystem>(
+    path: *const i8,
+    uid: libfuse::uid_t,
+    gid: libfuse::gid_t,
+    // may be NULL if not issued on an open file
+    _fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    // `chown(2)`: "If the owner or group is specified as -1, then that ID is not changed."
+    let uid = (uid != libfuse::uid_t::MAX).then_some(uid);
+    let gid = (gid != libfuse::gid_t::MAX).then_some(gid);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!(
+        "enter: chown('{}', {uid:?}, {gid:?})",
+        path.to_string_lossy()
+    );
+    try_errno!(call_into_user_code::<FS, _>("chown", || fs.chown(&path, uid, gid)));
+    debug!("return: chown => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+/// * `tv` - array of two timestamps, access time first, modification time second
+pub unsafe extern "C" fn utimens<FS: Filesystem>(
+    path: *const i8,
+    tv: *const libfuse::timespec,
+    // may be NULL if not issued on an open file
+    _fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!tv.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(tv.is_aligned(), Errno::EINVAL);
+
+    // SAFETY: checked for null and alignment above, libfuse always passes an array of two.
+    let [atime, mtime] = unsafe { *tv.cast::<[libfuse::timespec; 2]>() };
+    let atime =
+        try_errno!(UtimeSpec::try_from(atime).map_err(|e| (format!("invalid atime {atime:?}"), e)));
+    let mtime =
+        try_errno!(UtimeSpec::try_from(mtime).map_err(|e| (format!("invalid mtime {mtime:?}"), e)));
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!(
+        "enter: utimens('{}', atime={atime:?}, mtime={mtime:?})",
+        path.to_string_lossy()
+    );
+    try_errno!(call_into_user_code::<FS, _>("utimens", || fs.utimens(&path, atime, mtime)));
+    debug!("return: utimens => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+/// FUSE docs:
+///
+/// ```quote
+/// Read the target of a symbolic link
+///
+/// The buffer should be filled with a null terminated string. The buffer size argument includes the space for the
+/// terminating null character. If the linkname is too long to fit in the buffer, it should be truncated. The return
+/// value should be 0 for success.
+/// ```
+pub unsafe extern "C" fn readlink<FS: Filesystem>(
+    path: *const i8,
+    buf: *mut i8,
+    size: usize,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!buf.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(buf.is_aligned(), Errno::EINVAL);
+    // we need at least space for the nul terminator
+    ensure_errno!(size > 0, Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: readlink('{}')", path.to_string_lossy());
+    let ReadlinkRetVal { target } =
+        try_errno!(call_into_user_code::<FS, _>("readlink", || fs.readlink(&path)));
+    debug!("return: readlink => '{}'", target.to_string_lossy());
+
+    let target = target.as_os_str().as_bytes();
+    // a C string can't represent this, and silently truncating at the nul would point the link somewhere else.
+    ensure_errno!(!target.contains(&0), Errno::EIO);
+
+    // SAFETY: we checked that the pointer is aligned and non-null, libfuse guarantees `size` bytes behind it.
+    //         We leave the last byte for the terminator, so `n_copied < size`.
+    unsafe {
+        let n_copied = copy_to_c_buffer(target, buf, size - 1);
+        *buf.add(n_copied) = 0;
+    }
+
+    return 0;
+}
+
+pub unsafe extern "C" fn symlink<FS: Filesystem>(target: *const i8, link_path: *const i8) -> i32 {
+    ensure_errno!(!target.is_null(), Errno::EINVAL);
+    ensure_errno!(!link_path.is_null(), Errno::EINVAL);
+    ensure_errno!(target.is_aligned(), Errno::EINVAL);
+    ensure_errno!(link_path.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let target = try_errno!(unsafe { path_from_c_ptr(target) });
+    let link_path = try_errno!(unsafe { path_from_c_ptr(link_path) });
+
+    debug!(
+        "enter: symlink('{}' -> '{}')",
+        link_path.to_string_lossy(),
+        target.to_string_lossy()
+    );
+    try_errno!(call_into_user_code::<FS, _>("symlink", || fs.symlink(&target, &link_path)));
+    debug!("return: symlink => {}", link_path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn link<FS: Filesystem>(from: *const i8, to: *const i8) -> i32 {
+    ensure_errno!(!from.is_null(), Errno::EINVAL);
+    ensure_errno!(!to.is_null(), Errno::EINVAL);
+    ensure_errno!(from.is_aligned(), Errno::EINVAL);
+    ensure_errno!(to.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let from = try_errno!(unsafe { path_from_c_ptr(from) });
+    let to = try_errno!(unsafe { path_from_c_ptr(to) });
+
+    debug!(
+        "enter: link('{}', '{}')",
+        from.to_string_lossy(),
+        to.to_string_lossy()
+    );
+    try_errno!(call_into_user_code::<FS, _>("link", || fs.link(&from, &to)));
+    debug!("return: link => {}", to.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn write<FS: Filesystem>(
+    path: *const i8,
+    buf: *const i8,
+    size: usize,
+    offset: libc::off_t,
+    fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!buf.is_null(), Errno::EINVAL);
+    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(buf.is_aligned(), Errno::EINVAL);
+    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+
+    if size == 0 {
+        // nothing to write
+        return 0;
+    }
+    // we have to report the number of written bytes as i32, so we can't accept more than that.
+    ensure_errno!(size <= i32::MAX as usize, Errno::EDOM);
+    let offset = try_errno!(
+        u64::try_from(offset).map_err(|e| (format!("negative write offset: {e:#}"), Errno::EINVAL))
+    );
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+    // SAFETY: `buf` is non-null and aligned (checked above), libfuse guarantees it holds `size` bytes, and
+    //         `size <= i32::MAX` keeps us below the `isize::MAX` limit of `from_raw_parts`.
+    let data = unsafe { std::slice::from_raw_parts(buf.cast::<u8>(), size) };
+    // SAFETY: we check invariants at the function start, and only we write `fh`. Not set if `open` was skipped,
+    // see `Capability::NoOpenSupport`.
+    let file_handle = unsafe { borrow_file_handle::<FS::FileHandle>(&*fuse_file_info) };
+
+    debug!(
+        "enter: write('{}', size={size}, offset=0x{offset:x})",
+        path.to_string_lossy()
+    );
+    let WriteRetVal { n_written } = try_errno!(call_into_user_code::<FS, _>("write", || fs.write(
+        &path,
+        file_handle,
+        data,
+        offset
+    )));
+    debug!("return: write => {n_written}");
+
+    // user code claiming to have written more than it got is a bug, don't pass it on to the kernel.
+    ensure_errno!(n_written <= size, Errno::EIO);
+    // `n_written <= size <= i32::MAX`, see checks above.
+    return n_written as i32;
+}
+
+pub unsafe extern "C" fn create<FS: Filesystem>(
+    path: *const i8,
+    mode: libfuse::mode_t,
+    fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+    let flags = unsafe { OpenFlags((*fuse_file_info).flags) };
+    let mode = try_errno!(FileMode::try_from(mode).map_err(|e| (format!("{e:#}"), Errno::EINVAL)));
+
+    debug!(
+        "enter: create('{}', mode=0o{:o})",
+        path.to_string_lossy(),
+        mode.0
+    );
+    let OpenRetVal {
+        file_handle,
+        fuse_file_info: options,
+    } = try_errno!(call_into_user_code::<FS, _>("create", || fs.create(&path, mode, flags)));
+    debug!("return: create => {options:?}");
+
+    // SAFETY: we check invariants at the function start
+    let fuse_file_info = unsafe { &mut *fuse_file_info };
+    if let Some(options) = options {
+        options.apply_to(fuse_file_info);
+    }
+    store_file_handle(fuse_file_info, file_handle);
+
+    return 0;
+}
+
+pub unsafe extern "C" fn flush<FS: Filesystem>(
+    path: *const i8,
+    fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+    // SAFETY: we check invariants at the function start, and only we write `fh`. Not set if `open` was skipped,
+    // see `Capability::NoOpenSupport`.
+    let file_handle = unsafe { borrow_file_handle::<FS::FileHandle>(&*fuse_file_info) };
+
+    debug!("enter: flush('{}')", path.to_string_lossy());
+    try_errno!(call_into_user_code::<FS, _>("flush", || fs.flush(&path, file_handle)));
+    debug!("return: flush => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+/// Called exactly once per successful `open`/`create`, after the last file descriptor referring to it was closed.
+///
+/// Calls [`Filesystem::release`] and drops the [`Filesystem::FileHandle`] stored on open.
+pub unsafe extern "C" fn release<FS: Filesystem>(
+    path: *const i8,
+    fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
+    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+
+    // if this fails, the handle leaks. That's still better than running its `Drop` outside `call_into_user_code`.
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start, and only we write `fh`.
+    let file_handle = unsafe { take_file_handle::<FS::FileHandle>(&mut *fuse_file_info) };
+    let Some(file_handle) = file_handle else {
+        bail_errno!("no file handle set on `release`", Errno::EBADF);
+    };
+
+    // libfuse passes NULL if the file was already removed (and `hard_remove` is set). The handle has to be dropped
+    // either way, so we only skip the user hook then.
+    let path = if path.is_null() || !path.is_aligned() {
+        None
+    } else {
+        // SAFETY: checked right above
+        unsafe { path_from_c_ptr(path) }.ok()
+    };
+
+    debug!("enter: release({path:?})");
+    // `Drop` of the handle is user code too, so it might panic.
+    try_errno!(call_into_user_code::<FS, _>("release", || {
+        let result = path
+            .as_deref()
+            .map_or(Ok(()), |path| fs.release(path, &file_handle));
+        drop(file_handle);
+        result
+    }));
+    debug!("return: release");
+
+    return 0;
+}
+
+/// * `datasync` - non-zero if only the user data should be flushed, not the meta data
+pub unsafe extern "C" fn fsync<FS: Filesystem>(
+    path: *const i8,
+    datasync: i32,
+    fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!fuse_file_info.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(fuse_file_info.is_aligned(), Errno::EINVAL);
+
+    let datasync = datasync != 0;
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+    // SAFETY: we check invariants at the function start, and only we write `fh`. Not set if `open` was skipped,
+    // see `Capability::NoOpenSupport`.
+    let file_handle = unsafe { borrow_file_handle::<FS::FileHandle>(&*fuse_file_info) };
+
+    debug!(
+        "enter: fsync('{}', datasync={datasync})",
+        path.to_string_lossy()
+    );
+    try_errno!(call_into_user_code::<FS, _>("fsync", || fs.fsync(
+        &path,
+        file_handle,
+        datasync
+    )));
+    debug!("return: fsync => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn opendir<FS: Filesystem>(
+    path: *const i8,
+    _fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: opendir('{}')", path.to_string_lossy());
+    try_errno!(call_into_user_code::<FS, _>("opendir", || fs.opendir(&path)));
+    debug!("return: opendir => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn releasedir<FS: Filesystem>(
+    path: *const i8,
+    _fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: releasedir('{}')", path.to_string_lossy());
+    try_errno!(call_into_user_code::<FS, _>("releasedir", || fs.releasedir(&path)));
+    debug!("return: releasedir => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+/// * `datasync` - non-zero if only the user data should be flushed, not the meta data
+pub unsafe extern "C" fn fsyncdir<FS: Filesystem>(
+    path: *const i8,
+    datasync: i32,
+    _fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    let datasync = datasync != 0;
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!(
+        "enter: fsyncdir('{}', datasync={datasync})",
+        path.to_string_lossy()
+    );
+    try_errno!(call_into_user_code::<FS, _>("fsyncdir", || fs.fsyncdir(&path, datasync)));
+    debug!("return: fsyncdir => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn truncate<FS: Filesystem>(
+    path: *const i8,
+    size: libc::off_t,
+    // may be NULL if the truncate wasn't issued on an open file (`truncate(2)` vs. `ftruncate(2)`)
+    _fuse_file_info: *mut libfuse::fuse_file_info,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    let Ok(size) = u64::try_from(size) else {
+        bail_errno!(format!("negative size for truncate: {size}"), Errno::EINVAL);
+    };
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: truncate('{}', size={size})", path.to_string_lossy());
+    try_errno!(call_into_user_code::<FS, _>("truncate", || fs.truncate(&path, size)));
+    debug!("return: truncate => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn unlink<FS: Filesystem>(path: *const i8) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: unlink('{}')", path.to_string_lossy());
+    try_errno!(call_into_user_code::<FS, _>("unlink", || fs.unlink(&path)));
+    debug!("return: unlink => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn mkdir<FS: Filesystem>(path: *const i8, mode: libfuse::mode_t) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+    // the kernel strips the file type bits for `mkdir`, so add them back to hand out a consistent `FileMode`.
+    let mode = try_errno!(
+        FileMode::try_from(libfuse::S_IFDIR | (mode & !libfuse::S_IFMT))
+            .map_err(|e| (format!("{e:#}"), Errno::EINVAL))
+    );
+
+    debug!(
+        "enter: mkdir('{}', mode=0o{:o})",
+        path.to_string_lossy(),
+        mode.0
+    );
+    try_errno!(call_into_user_code::<FS, _>("mkdir", || fs.mkdir(&path, mode)));
+    debug!("return: mkdir => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn rmdir<FS: Filesystem>(path: *const i8) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: rmdir('{}')", path.to_string_lossy());
+    try_errno!(call_into_user_code::<FS, _>("rmdir", || fs.rmdir(&path)));
+    debug!("return: rmdir => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn rename<FS: Filesystem>(from: *const i8, to: *const i8, flags: u32) -> i32 {
+    ensure_errno!(!from.is_null(), Errno::EINVAL);
+    ensure_errno!(!to.is_null(), Errno::EINVAL);
+    ensure_errno!(from.is_aligned(), Errno::EINVAL);
+    ensure_errno!(to.is_aligned(), Errno::EINVAL);
+
+    let flags = try_errno!(
+        RenameFlags::try_from(flags).map_err(|e| (format!("invalid rename flags 0x{flags:x}"), e))
+    );
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let from = try_errno!(unsafe { path_from_c_ptr(from) });
+    let to = try_errno!(unsafe { path_from_c_ptr(to) });
+
+    debug!(
+        "enter: rename('{}', '{}', {flags:?})",
+        from.to_string_lossy(),
+        to.to_string_lossy()
+    );
+    try_errno!(call_into_user_code::<FS, _>("rename", || fs.rename(&from, &to, flags)));
+    debug!("return: rename => {}", to.to_string_lossy());
+
+    return 0;
+}
+
+pub unsafe extern "C" fn statfs<FS: Filesystem>(
+    path: *const i8,
+    statvfs_out: *mut libfuse::statvfs,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!statvfs_out.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(statvfs_out.is_aligned(), Errno::EINVAL);
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: statfs('{}')", path.to_string_lossy());
+    let StatfsRetVal { stat_fs } =
+        try_errno!(call_into_user_code::<FS, _>("statfs", || fs.statfs(&path)));
+    debug!("return: statfs => {stat_fs:?}");
+
+    // SAFETY: we assume that the outptr received by libfuse is not dangling. We can check for alignment and
+    // non-null-ity, but invalid memory addresses will not be caught.
+    unsafe {
+        *statvfs_out = *stat_fs;
+    }
+
+    return 0;
+}
+
+/// The kernel connection is negotiated here, first by the crate, then by [`Filesystem::init`].
+///
+/// Returns the `private_data` of `fuse_context`, which `fuse_main` sets to `NULL`.
+pub unsafe extern "C" fn init<FS: Filesystem>(
+    conn: *mut libfuse::fuse_conn_info,
+    cfg: *mut libfuse::fuse_config,
+) -> *mut c_void {
+    if conn.is_null() || !conn.is_aligned() || cfg.is_null() || !cfg.is_aligned() {
+        error!(
+            "init: invalid `fuse_conn_info` or `fuse_config` pointer, keeping libfuse's defaults"
+        );
+        return ptr::null_mut();
+    }
+    // SAFETY: checked above, libfuse keeps both valid during `init`
+    let (conn, cfg) = unsafe { (&mut *conn, &mut *cfg) };
+
+    let mut conn_info = ConnectionInfo::new(conn);
+    let mut fuse_config = FuseConfig::new(cfg);
+
+    let fs = match fetch_fs_from_registry::<FS>() {
+        Ok(fs) => fs,
+        Err((e, _)) => {
+            error!("init: {e}");
+            conn_info.apply_to(conn);
+            return ptr::null_mut();
+        }
+    };
+    debug!("enter: init({conn_info:?}, {fuse_config:?})");
+    if let Err((e, _)) = call_into_user_code::<FS, _>("init", || {
+        fs.init(&mut conn_info, &mut fuse_config);
+        Ok(())
+    }) {
+        error!("init: {e}");
+    }
+    debug!("return: init => want={:?}", conn_info.want);
+    conn_info.apply_to(conn);
+    fuse_config.apply_to(cfg);
+
+    ptr::null_mut()
+}
+
+/// Runs [`Filesystem::destroy`], before the crate clears the registry.
+pub unsafe extern "C" fn destroy<FS: Filesystem>(_private_data: *mut c_void) {
+    let fs = match fetch_fs_from_registry::<FS>() {
+        Ok(fs) => fs,
+        Err((e, _)) => {
+            error!("destroy: {e}");
+            return;
+        }
+    };
+    debug!("enter: destroy()");
+    if let Err((e, _)) = call_into_user_code::<FS, _>("destroy", || {
+        fs.destroy();
+        Ok(())
+    }) {
+        error!("destroy: {e}");
+    }
+    debug!("return: destroy");
+}
+
+pub unsafe extern "C" fn getxattr<FS: Filesystem>(
+    path: *const i8,
+    name: *const i8,
+    value: *mut i8,
+    size: usize,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!name.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(name.is_aligned(), Errno::EINVAL);
+    // `value` may be NULL if `size == 0`, `reply_with_size_probe` checks that.
+
+    // SAFETY: we check invariants at the function start
+    let name = try_errno!(unsafe { xattr_name_from_c_ptr(name) });
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!(
+        "enter: getxattr('{}', '{name}', size={size})",
+        path.to_string_lossy()
+    );
+    let GetxattrRetVal { value: xattr_value } =
+        try_errno!(call_into_user_code::<FS, _>("getxattr", || fs.getxattr(&path, &name)));
+    debug!("return: getxattr => {} bytes", xattr_value.len());
+
+    // SAFETY: libfuse guarantees `size` bytes behind `value`.
+    return try_errno!(unsafe { reply_with_size_probe(&xattr_value, value, size) });
+}
+
+pub unsafe extern "C" fn setxattr<FS: Filesystem>(
+    path: *const i8,
+    name: *const i8,
+    value: *const i8,
+    size: usize,
+    flags: i32,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!name.is_null(), Errno::EINVAL);
+    ensure_errno!(size == 0 || !value.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(name.is_aligned(), Errno::EINVAL);
+    ensure_errno!(value.is_aligned(), Errno::EINVAL);
+    ensure_errno!(isize::try_from(size).is_ok(), Errno::E2BIG);
+
+    let mode = try_errno!(
+        XattrSetMode::try_from(flags)
+            .map_err(|e| (format!("invalid setxattr flags 0x{flags:x}"), e))
+    );
+    // SAFETY: we check invariants at the function start
+    let name = try_errno!(unsafe { xattr_name_from_c_ptr(name) });
+    let xattr_value = if size == 0 {
+        &[][..]
+    } else {
+        // SAFETY: non-null and aligned (checked above), libfuse guarantees `size` bytes, `size <= isize::MAX`.
+        unsafe { std::slice::from_raw_parts(value.cast::<u8>(), size) }
+    };
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!(
+        "enter: setxattr('{}', '{name}', size={size}, {mode:?})",
+        path.to_string_lossy()
+    );
+    try_errno!(call_into_user_code::<FS, _>("setxattr", || fs.setxattr(
+        &path,
+        &name,
+        xattr_value,
+        mode
+    )));
+    debug!("return: setxattr => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+/// * `list` - receives the attribute names, each one nul-terminated
+pub unsafe extern "C" fn listxattr<FS: Filesystem>(
+    path: *const i8,
+    list: *mut i8,
+    size: usize,
+) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    // `list` may be NULL if `size == 0`, `reply_with_size_probe` checks that.
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!(
+        "enter: listxattr('{}', size={size})",
+        path.to_string_lossy()
+    );
+    let ListxattrRetVal { names } =
+        try_errno!(call_into_user_code::<FS, _>("listxattr", || fs.listxattr(&path)));
+    debug!("return: listxattr => {names:?}");
+
+    // `XattrName` can't contain nul bytes, so this is unambiguous.
+    let mut serialized = Vec::new();
+    for name in names {
+        serialized.extend_from_slice(name.to_string().as_bytes());
+        serialized.push(0);
+    }
+
+    // SAFETY: libfuse guarantees `size` bytes behind `list`.
+    return try_errno!(unsafe { reply_with_size_probe(&serialized, list, size) });
+}
+
+pub unsafe extern "C" fn removexattr<FS: Filesystem>(path: *const i8, name: *const i8) -> i32 {
+    ensure_errno!(!path.is_null(), Errno::EINVAL);
+    ensure_errno!(!name.is_null(), Errno::EINVAL);
+    ensure_errno!(path.is_aligned(), Errno::EINVAL);
+    ensure_errno!(name.is_aligned(), Errno::EINVAL);
+
+    // SAFETY: we check invariants at the function start
+    let name = try_errno!(unsafe { xattr_name_from_c_ptr(name) });
+
+    let fs = try_errno!(fetch_fs_from_registry::<FS>());
+
+    // SAFETY: we check invariants at the function start
+    let path = try_errno!(unsafe { path_from_c_ptr(path) });
+
+    debug!("enter: removexattr('{}', '{name}')", path.to_string_lossy());
+    try_errno!(call_into_user_code::<FS, _>("removexattr", || fs.removexattr(&path, &name)));
+    debug!("return: removexattr => {}", path.to_string_lossy());
+
+    return 0;
+}
+
+/// Registry entry of one file system type, shared by all threads serving it.
+///
+/// The registry can't remove entries, so there is one slot per type, created on first use.
+struct FsSlot<FS> {
+    fs: RwLock<Option<Arc<FS>>>,
+    /// Set once user code panicked. Worker threads may already hold an `Arc` of the file system, so
+    /// [`call_into_user_code`] checks this flag right before entering user code.
+    poisoned: AtomicBool,
+}
+
+/// Serializes creating slots, so concurrent first registrations of a type don't replace each other's slot.
+static FS_SLOT_CREATION: Mutex<()> = Mutex::new(());
+
+fn fs_slot<FS: Filesystem>() -> Arc<FsSlot<FS>> {
+    if let Ok(slot) = state::get::<FsSlot<FS>>() {
+        return slot;
+    }
+    let _guard = FS_SLOT_CREATION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Ok(slot) = state::get::<FsSlot<FS>>() {
+        return slot;
+    }
+    state::register(FsSlot::<FS> {
+        fs: RwLock::new(None),
+        poisoned: AtomicBool::new(false),
+    });
+    state::get::<FsSlot<FS>>().expect("registered right above")
+}
+
+/// Makes `fs` available to the callbacks (on any thread), lifting a poisoning from an earlier run of this type.
+///
+/// Fails if a file system of the same type is still registered, see [`unregister_fs`].
+fn register_fs<FS: Filesystem>(fs: FS) -> Result<()> {
+    let slot = fs_slot::<FS>();
+    let mut entry = slot.fs.write().unwrap_or_else(PoisonError::into_inner);
+    if entry.is_some() {
+        bail!(
+            "a file system of type `{}` is already mounted",
+            std::any::type_name::<FS>()
+        );
+    }
+    *entry = Some(Arc::new(fs));
+    slot.poisoned.store(false, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Empties the slot of `FS`, leaving other file system types alone. Calls in flight keep their `Arc`.
+fn unregister_fs<FS: Filesystem>() {
+    if let Ok(slot) = state::get::<FsSlot<FS>>() {
+        *slot.fs.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Returns a clone of the `Arc`, so the file system stays alive for the whole call even if another thread
+/// unregisters it in the meantime.
+fn fetch_fs_from_registry<FS: Filesystem>() -> Result<Arc<FS>, (String, Errno)> {
+    let not_found = |e: &dyn fmt::Display| {
+        (
+            format!(
+                "State lookup for `{}` failed. Registry corrupted? ({e:#})",
+                std::any::type_name::<FS>()
+            ),
+            Errno::ENOTRECOVERABLE,
+        )
+    };
+    let slot = state::get::<FsSlot<FS>>().map_err(|e| not_found(&e))?;
+    let entry = slot.fs.read().unwrap_or_else(PoisonError::into_inner);
+    entry.clone().ok_or_else(|| not_found(&"not registered"))
+}
+
+fn call_into_user_code<FS: Filesystem, T>(
+    method: &str,
+    user_fn: impl FnOnce() -> Result<T, Errno>,
+) -> Result<T, (String, Errno)> {
+    let fs = std::any::type_name::<FS>();
+    let slot = state::get::<FsSlot<FS>>();
+    let refusal = match &slot {
+        Err(e) => Some(format!(
+            "State lookup for `{fs}` failed. Registry corrupted? ({e:#})"
+        )),
+        Ok(slot) if slot.poisoned.load(Ordering::SeqCst) => Some(format!(
+            "`{fs}` panicked earlier, refusing to call `{fs}::{method}`"
+        )),
+        Ok(_) => None,
+    };
+    if let Some(refusal) = refusal {
+        // `user_fn` may own values with a `Drop` from user code (like a file handle), which can panic as well
+        if std::panic::catch_unwind(core::panic::AssertUnwindSafe(move || drop(user_fn))).is_err() {
+            error!("PANIC while dropping the arguments of `{fs}::{method}`");
+        }
+        return Err((refusal, Errno::ENOTRECOVERABLE));
+    }
+    std::panic::catch_unwind(core::panic::AssertUnwindSafe(user_fn))
+        .map_err(|panic| {
+            // abort, since internal state of filesystem impl can now be inconsistent. other worker threads may
+            // still be inside user code, but no new calls of this type are started.
+            if let Ok(slot) = &slot {
+                slot.poisoned.store(true, Ordering::SeqCst);
+            }
+            (
+                format!("PANIC on `{fs}::{method}`:\n\n{panic:?}\n"),
+                Errno::ENOTRECOVERABLE,
+            )
+        })
+        .and_then(|inner| inner.map_err(|e| (format!("Error in user code `{fs}::{method}`"), e)))
+}
+
+/// # Safety
+///
+/// - `c_str` - is a valid pointer (non-dangling, aligned), is nul-terminated
+///
+/// Since we copy the string instead of referencing it, aliasing and mutation of the original c string
+/// are not as important.
+unsafe fn path_from_c_ptr(c_str: *const c_char) -> Result<PathBuf, (String, Errno)> {
+    let path = unsafe { CStr::from_ptr(c_str) };
+    let path_utf8: &str = match path.to_str() {
+        Ok(path) => path,
+        Err(utf8_error) => {
+            return Err((
+                format!("path is not valid UTF-8: {utf8_error:#}"),
+                Errno::EINVAL,
+            ));
+        }
+    };
+
+    // TODO (maybe) copy path because what lifetime should this &Path have? we can't depend it on a ptr.
+    let path_utf8 = PathBuf::from(path_utf8);
+    Ok(path_utf8)
+}
+
+/// Moves `file_handle` to the heap and hands the pointer to libfuse via `fuse_file_info.fh`.
+///
+/// Every handle stored this way has to be reclaimed exactly once with [`take_file_handle`], which `release` does.
+fn store_file_handle<FH: Send + Sync + 'static>(
+    fuse_file_info: &mut libfuse::fuse_file_info,
+    file_handle: FH,
+) {
+    let raw = Box::into_raw(Box::new(file_handle));
+    // `usize` -> `u64` is lossless on all supported targets. Boxes of ZSTs are dangling, but never null.
+    fuse_file_info.fh = raw.expose_provenance() as u64;
+}
+
+/// Returns `None` if no handle is stored.
+///
+/// # Safety
+///
+/// - `fuse_file_info.fh` is either `0`, or was set by [`store_file_handle`] with the same `FH` and not taken yet
+unsafe fn borrow_file_handle<FH>(fuse_file_info: &libfuse::fuse_file_info) -> Option<&FH> {
+    let raw = ptr::with_exposed_provenance::<FH>(usize::try_from(fuse_file_info.fh).ok()?);
+    unsafe { raw.as_ref() }
+}
+
+/// Takes back ownership of the handle, and resets `fuse_file_info.fh`, so a second call returns `None`, instead of
+/// double-freeing.
+///
+/// # Safety
+///
+/// - `fuse_file_info.fh` is either `0`, or was set by [`store_file_handle`] with the same `FH` and not taken yet
+/// - no references obtained by [`borrow_file_handle`] are alive
+unsafe fn take_file_handle<FH>(fuse_file_info: &mut libfuse::fuse_file_info) -> Option<Box<FH>> {
+    let raw = ptr::with_exposed_provenance_mut::<FH>(usize::try_from(fuse_file_info.fh).ok()?);
+    if raw.is_null() {
+        return None;
+    }
+    fuse_file_info.fh = 0;
+    Some(unsafe { Box::from_raw(raw) })
+}
+
+/// # Safety
+///
+/// - `c_str` - is a valid pointer (non-dangling, aligned), is nul-terminated
+unsafe fn xattr_name_from_c_ptr(c_str: *const c_char) -> Result<XattrName, (String, Errno)> {
+    let name = unsafe { CStr::from_ptr(c_str) };
+    let name = name.to_str().map_err(|e| {
+        (
+            format!("xattr name is not valid UTF-8: {e:#}"),
+            Errno::EINVAL,
+        )
+    })?;
+    name.parse::<XattrName>()
+        .map_err(|e| (format!("{e:#}"), e.into()))
+}
+
+/// Implements the size-probing protocol of `getxattr(2)`/`listxattr(2)`:
+///
+/// - `size == 0` - don't copy anything, only return the size `data` needs
+/// - `size < data.len()` - `ERANGE`
+/// - otherwise copy `data` and return its length
+///
+/// # Safety
+///
+/// - `buf` - if `size != 0`: is a valid pointer to at least `size` writable bytes, which don't overlap `data`
+unsafe fn reply_with_size_probe(
+    data: &[u8],
+    buf: *mut c_char,
+    size: usize,
+) -> Result<i32, (String, Errno)> {
+    let Ok(n_bytes) = i32::try_from(data.len()) else {
+        return Err((
+            format!("{} bytes don't fit into the reply", data.len()),
+            Errno::E2BIG,
+        ));
+    };
+    if size == 0 {
+        return Ok(n_bytes);
+    }
+    if size < data.len() {
+        return Err((
+            format!("buffer too small: {size} < {}", data.len()),
+            Errno::ERANGE,
+        ));
+    }
+    if buf.is_null() || !buf.is_aligned() {
+        return Err(("invalid buffer pointer".to_owned(), Errno::EINVAL));
+    }
+    // SAFETY: checked above, and as per our contract
+    unsafe {
+        copy_to_c_buffer(data, buf, size);
+    }
+    Ok(n_bytes)
+}
+
+/// Copies as much of `src` into `buf` as fits into `size` bytes, and returns the number of bytes copied.
+///
+/// No nul terminator is written, callers that hand out C strings have to do that themselves.
+///
+/// # Safety
+///
+/// - `buf` - is a valid pointer (non-null, aligned) to at least `size` writable bytes, which don't overlap `src`
+unsafe fn copy_to_c_buffer(src: &[u8], buf: *mut c_char, size: usize) -> usize {
+    let n_bytes = src.len().min(size);
+    unsafe {
+        ptr::copy_nonoverlapping(src.as_ptr(), buf.cast::<u8>(), n_bytes);
+    }
+    n_bytes
+}
+
+/*fn call_with_catch_unwind<FS: Filesystem, T>(
+    fun: impl FnOnce() -> T,
+    method: &'static str,
+) -> Result<T, Errno> {
+    use core::panic::AssertUnwindSafe;
+    use std::panic::catch_unwind;
+
+    result.map_err(|e| {
+        error!(
+            "PANIC on `{fs}::{method}`:\n\n{e:?}\n\nreturning ENOSYS\n",
+            fs = std::any::type_name::<FS>()
+        );
+        Errno::ENOTRECOVERABLE
+    })
+}*/
+
+// ALTERNATIVE: store filesystem handles inside a vector, and use some fuse field to smuggle a vector offset
+//
 //static STATE: Vec<Arc<&dyn Filesystem>> = Vec::new();
 
-// ALTERNATIVE: manually spawn fuse workers. Store handle like this:
-// pub struct FuseHandle(i32);
+// ALTERNATIVE: manually spawn fuse workers. Store handle like this:
+// pub struct FuseHandle(i32);
+
+/// usage: /home/ra1n/.cache/cargo_target/debug/examples/hello [options] <mountpoint>
+///
+/// FUSE options:
+///     -h   --help            print help
+///     -V   --version         print version
+///     -d   -o debug          enable debug output (implies -f)
+///     -f                     foreground operation
+///     -s                     disable multi-threaded operation
+///     -o clone_fd            use separate fuse device fd for each thread
+///                            (may improve performance)
+///     -o max_idle_threads    the maximum number of idle worker threads
+///                            allowed (default: -1)
+///     -o max_threads         the maximum number of worker threads
+///                            allowed (default: 10)
+///     -o kernel_cache        cache files in kernel
+///     -o [no]auto_cache      enable caching based on modification times (off)
+///     -o no_rofd_flush       disable flushing of read-only fd on close (off)
+///     -o umask=M             set file permissions (octal)
+///     -o fmask=M             set file permissions (octal)
+///     -o dmask=M             set dir  permissions (octal)
+///     -o uid=N               set file owner
+///     -o gid=N               set file group
+///     -o entry_timeout=T     cache timeout for names (1.0s)
+///     -o negative_timeout=T  cache timeout for deleted names (0.0s)
+///     -o attr_timeout=T      cache timeout for attributes (1.0s)
+///     -o ac_attr_timeout=T   auto cache timeout for attributes (attr_timeout)
+///     -o noforget            never forget cached inodes
+///     -o remember=T          remember cached inodes for T seconds (0s)
+///     -o modules=M1[:M2...]  names of modules to push onto filesystem stack
+///     -o allow_other         allow access by all users
+///     -o allow_root          allow access by root
+///     -o auto_unmount        auto unmount on process termination
+///
+/// Options for subdir module:
+///     -o subdir=DIR           prepend this directory to all paths (mandatory)
+///     -o [no]rellinks         transform absolute symlinks to relative
+///
+/// Options for iconv module:
+///     -o from_code=CHARSET   original encoding of file names (default: UTF-8)
+///     -o to_code=CHARSET     new encoding of the file names (default: UTF-8)
+///
+pub fn fuse_main<FS: Filesystem>(
+    fs: FS,
+    mount_point: impl AsRef<Path>,
+    options: &MountOptions,
+) -> Result<()> {
+    let Some(mount_point) = mount_point.as_ref().to_str() else {
+        bail!(
+            "mount point '{}' is not valid UTF-8",
+            mount_point.as_ref().to_string_lossy()
+        )
+    };
+
+    // `fuse_main` reads the mount point as positional arg. we always run single-threaded, and in the foreground.
+    let args = FuseArgs::new(
+        [
+            program_name(),
+            mount_point.to_owned(),
+            "-f".into(),
+            "-s".into(),
+        ]
+        .into_iter()
+        .chain(options.to_args()),
+    )?;
+
+    register_fs(fs)?;
+
+    let fuse_ops = fuse_operations::<FS>();
+
+    unsafe {
+        // fuse_main_fn(argc: ::std::os::raw::c_int, argv: *mut *mut ::std::os::raw::c_char,
+        //              op: *const fuse_operations, user_data: *mut ::std::os::raw::c_void) -> ::std::os::raw::c_int
+        let errno = libfuse::fuse_main_fn(
+            args.0.argc,
+            args.0.argv,
+            &fuse_ops as *const libfuse::fuse_operations,
+            ptr::null_mut(),
+        );
+        // `fuse_main` has called `destroy` by now
+        unregister_fs::<FS>();
+        if errno != 0 {
+            bail!("`libfuse::fuse_main_fn()` returned non-zero status ({errno})");
+        }
+    }
+
+    // let _fuse_args = libfuse::fuse_args {
+    //     argc: todo!(),
+    //     argv: todo!(),
+    //     allocated: todo!(),
+    // };
+
+    // let _fuse_fs = unsafe {
+    //     libfuse::fuse_fs_new(&fuse_ops, std::mem::size_of_val(&fuse_ops), ptr::null_mut())
+    // };
+    //let fuse = unsafe { libfuse::_fuse_new_31(args, op, op_size, version, user_data) };
+
+    // SAFETY: use mut_ptr() to not trust the c code to not mutate?
+    //let fuse_handle = unsafe { libfuse::fuse_mount(fuse_fs, mount_point_c_str.as_ptr()) };
+
+    Ok(())
+}
+
+/// Worker thread settings for [`fuse_main_mt`], see
+/// <https://libfuse.github.io/doxygen/structfuse__loop__config.html>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopConfig {
+    /// Upper bound of worker threads handling requests concurrently.
+    pub max_threads: NonZeroU32,
+    /// Number of idle worker threads kept around, `None` keeps all of them.
+    pub max_idle_threads: Option<u32>,
+    /// Give every worker thread its own `/dev/fuse` file descriptor.
+    pub clone_fd: bool,
+}
+
+impl Default for LoopConfig {
+    /// libfuse's defaults: 10 threads, no limit on idle threads, one shared file descriptor.
+    fn default() -> Self {
+        Self {
+            max_threads: NonZeroU32::new(10).expect("non-zero"),
+            max_idle_threads: None,
+            clone_fd: false,
+        }
+    }
+}
+
+/// Owns a `fuse_loop_config` allocated by libfuse.
+struct LoopConfigHandle(*mut libfuse::fuse_loop_config);
+
+impl LoopConfigHandle {
+    fn new(config: LoopConfig) -> Result<Self> {
+        // SAFETY: no preconditions
+        let handle = unsafe { libfuse::fuse_loop_cfg_create() };
+        if handle.is_null() {
+            bail!("`libfuse::fuse_loop_cfg_create()` failed");
+        }
+        // SAFETY: `handle` was just created by libfuse and is non-null
+        unsafe {
+            libfuse::fuse_loop_cfg_set_max_threads(handle, config.max_threads.get());
+            if let Some(max_idle_threads) = config.max_idle_threads {
+                libfuse::fuse_loop_cfg_set_idle_threads(handle, max_idle_threads);
+            }
+            libfuse::fuse_loop_cfg_set_clone_fd(handle, config.clone_fd.into());
+        }
+        Ok(Self(handle))
+    }
+}
+
+impl Drop for LoopConfigHandle {
+    fn drop(&mut self) {
+        // SAFETY: created by `fuse_loop_cfg_create` and only destroyed here
+        unsafe { libfuse::fuse_loop_cfg_destroy(self.0) };
+    }
+}
+
+// SAFETY: the config is only read by libfuse once the loop starts, which happens on the thread we send it to
+unsafe impl Send for LoopConfigHandle {}
+
+/// A `struct fuse` that is mounted, but not necessarily served yet.
+///
+/// Torn down with [`MountedFuse::unmount`] followed by [`MountedFuse::destroy`], once no loop runs anymore.
+#[derive(Debug)]
+struct MountedFuse {
+    fuse: *mut libfuse::fuse,
+    /// [`unregister_fs`] for the type it serves
+    unregister: fn(),
+}
+
+// SAFETY: while a loop runs, other threads only call `fuse_exit`, which just sets a flag. `fuse_unmount` (closing
+// the device fd the loop reads from) and `fuse_destroy` are only called once no loop runs anymore.
+unsafe impl Send for MountedFuse {}
+unsafe impl Sync for MountedFuse {}
+
+impl MountedFuse {
+    /// Registers `fs`, and creates and mounts a `struct fuse` serving it at `mount_point`.
+    fn new<FS: Filesystem>(fs: FS, mount_point: &Path, options: &MountOptions) -> Result<Self> {
+        let mount_point_c_str =
+            CString::new(mount_point.as_os_str().as_bytes()).wrap_err_with(|| {
+                format!(
+                    "mount point '{}' is not a valid CString",
+                    mount_point.display()
+                )
+            })?;
+
+        register_fs(fs)?;
+
+        let mut args = match FuseArgs::new(iter::once(program_name()).chain(options.to_args())) {
+            Ok(args) => args,
+            Err(e) => {
+                unregister_fs::<FS>();
+                return Err(e);
+            }
+        };
+
+        let fuse_ops = fuse_operations::<FS>();
+
+        // SAFETY: `args` is a valid `fuse_args`, and libfuse copies the operations table and the options it keeps.
+        let fuse = unsafe {
+            libfuse::fuse_new_fn(
+                &raw mut args.0,
+                &raw const fuse_ops,
+                size_of_val(&fuse_ops),
+                ptr::null_mut(),
+            )
+        };
+        drop(args);
+        if fuse.is_null() {
+            unregister_fs::<FS>();
+            bail!("`libfuse::fuse_new()` failed, see stderr");
+        }
+
+        // SAFETY: `fuse` is non-null
+        let errno = unsafe { libfuse::fuse_mount(fuse, mount_point_c_str.as_ptr()) };
+        if errno != 0 {
+            // SAFETY: not mounted and no loop running, so nothing else uses it
+            unsafe { libfuse::fuse_destroy(fuse) };
+            unregister_fs::<FS>();
+            bail!(
+                "`libfuse::fuse_mount()` on '{}' returned non-zero status ({errno})",
+                mount_point.display()
+            );
+        }
+        Ok(Self {
+            fuse,
+            unregister: unregister_fs::<FS>,
+        })
+    }
+
+    fn as_ptr(&self) -> *mut libfuse::fuse {
+        self.fuse
+    }
+
+    /// Makes a running loop return once it handles its next request (or the device is gone).
+    fn exit(&self) {
+        // SAFETY: `self.fuse` is valid until `destroy`
+        unsafe { libfuse::fuse_exit(self.fuse) };
+    }
+
+    /// Closes the device fd and detaches the mount point, unless that already happened.
+    ///
+    /// Must not be called while a loop runs, the loop may still be reading the fd. See [`detach_mount_point`].
+    fn unmount(&self) {
+        // SAFETY: `self.fuse` is valid until `destroy`
+        unsafe { libfuse::fuse_unmount(self.fuse) };
+    }
+
+    /// Frees the `struct fuse`, which calls [`Filesystem::destroy`] if the session was initialized, and unregisters
+    /// its file system afterwards.
+    ///
+    /// # Safety
+    ///
+    /// - no loop is running on it anymore
+    unsafe fn destroy(self) {
+        unsafe { libfuse::fuse_destroy(self.fuse) };
+        (self.unregister)();
+    }
+}
+
+/// Lazily detaches `mount_point`, without touching the device fd a running loop may be reading. The kernel ends the
+/// connection once the mount is gone, which makes the loop's pending read return.
+///
+/// Unprivileged mounts are detached with libfuse's setuid helper `fusermount3`.
+fn detach_mount_point(mount_point: &Path) -> Result<()> {
+    match nix::mount::umount2(mount_point, nix::mount::MntFlags::MNT_DETACH) {
+        // not mounted (anymore), so the loop returns on its own
+        Ok(()) | Err(Errno::EINVAL) => return Ok(()),
+        Err(Errno::EPERM) => {}
+        Err(e) => {
+            return Err(Report::new(e).wrap_err(format!("detaching '{}'", mount_point.display())));
+        }
+    }
+    let status = std::process::Command::new("fusermount3")
+        .args(["-u", "-z", "--"])
+        .arg(mount_point)
+        .status()
+        .wrap_err("running `fusermount3`")?;
+    if !status.success() {
+        bail!(
+            "`fusermount3 -u -z` on '{}' failed ({status})",
+            mount_point.display()
+        );
+    }
+    Ok(())
+}
+
+/// Like [`fuse_main`], but requests are handled concurrently by a pool of worker threads (`fuse_loop_mt`),
+/// configured by `config`.
+///
+/// Runs in the foreground and returns once the file system is unmounted or
+/// the process receives `SIGINT`/`SIGTERM`/`SIGHUP`.
+pub fn fuse_main_mt<FS: Filesystem>(
+    fs: FS,
+    mount_point: impl AsRef<Path>,
+    options: &MountOptions,
+    config: LoopConfig,
+) -> Result<()> {
+    let loop_config = LoopConfigHandle::new(config)?;
+    let fuse = MountedFuse::new(fs, mount_point.as_ref(), options)?;
+
+    // SAFETY: `fuse` is mounted and valid until we destroy it below
+    let errno = unsafe {
+        let session = libfuse::fuse_get_session(fuse.as_ptr());
+        let errno = libfuse::fuse_set_signal_handlers(session);
+        if errno == 0 {
+            let errno = libfuse::fuse_loop_mt(fuse.as_ptr(), loop_config.0);
+            libfuse::fuse_remove_signal_handlers(session);
+            errno
+        } else {
+            errno
+        }
+    };
+    fuse.unmount();
+    // SAFETY: the loop has returned, so no callback is running anymore
+    unsafe { fuse.destroy() };
+
+    if errno != 0 {
+        bail!("`libfuse::fuse_loop_mt()` returned non-zero status ({errno})");
+    }
+    Ok(())
+}
+
+/// Mounts `fs` at `mount_point` and serves it single-threaded (`fuse_loop`) on a background thread, until the
+/// returned [`MountHandle`] is unmounted or dropped.
+///
+/// No signal handlers are installed. Only one file system per `FS` type can be mounted at a time.
+pub fn mount<FS: Filesystem>(
+    fs: FS,
+    mount_point: impl AsRef<Path>,
+    options: &MountOptions,
+) -> Result<MountHandle> {
+    MountHandle::spawn(fs, mount_point.as_ref(), options, None)
+}
+
+/// Like [`mount`], but requests are handled by a pool of worker threads (`fuse_loop_mt`), configured by `config`.
+pub fn mount_mt<FS: Filesystem>(
+    fs: FS,
+    mount_point: impl AsRef<Path>,
+    options: &MountOptions,
+    config: LoopConfig,
+) -> Result<MountHandle> {
+    let loop_config = LoopConfigHandle::new(config)?;
+    MountHandle::spawn(fs, mount_point.as_ref(), options, Some(loop_config))
+}
+
+/// A file system mounted by [`mount`] or [`mount_mt`], served on a background thread.
+///
+/// Dropping it unmounts the file system, like [`MountHandle::unmount`] does, but only logs errors.
+#[derive(Debug)]
+pub struct MountHandle {
+    mount_point: PathBuf,
+    /// `None` once torn down
+    session: Option<(Arc<MountedFuse>, JoinHandle<i32>)>,
+}
+
+impl MountHandle {
+    fn spawn<FS: Filesystem>(
+        fs: FS,
+        mount_point: &Path,
+        options: &MountOptions,
+        loop_config: Option<LoopConfigHandle>,
+    ) -> Result<Self> {
+        let fuse = Arc::new(MountedFuse::new(fs, mount_point, options)?);
+        let session_loop = {
+            let fuse = Arc::clone(&fuse);
+            std::thread::Builder::new()
+                .name(format!("fuse '{}'", mount_point.display()))
+                .spawn(move || {
+                    // SAFETY: `fuse` is mounted, and only destroyed after this thread has been joined
+                    unsafe {
+                        match loop_config {
+                            Some(loop_config) => {
+                                libfuse::fuse_loop_mt(fuse.as_ptr(), loop_config.0)
+                            }
+                            None => libfuse::fuse_loop(fuse.as_ptr()),
+                        }
+                    }
+                })
+        };
+        let session_loop = match session_loop {
+            Ok(session_loop) => session_loop,
+            Err(e) => {
+                fuse.unmount();
+                if let Some(fuse) = Arc::into_inner(fuse) {
+                    // SAFETY: the loop never started
+                    unsafe { fuse.destroy() };
+                }
+                return Err(Report::new(e).wrap_err("spawning the session loop thread"));
+            }
+        };
+        Ok(Self {
+            mount_point: mount_point.to_owned(),
+            session: Some((fuse, session_loop)),
+        })
+    }
+
+    #[must_use]
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    /// Detaches the mount point, waits for the session loop to return, then closes the session and frees libfuse's
+    /// state.
+    ///
+    /// Fails if the session loop returned an error or panicked. If the mount point can't be detached, the loop is
+    /// left running (and the file system mounted), since waiting for it could block forever. The handle stays
+    /// mounted then, so this can be retried.
+    pub fn unmount(&mut self) -> Result<()> {
+        let Some((fuse, session_loop)) = self.session.take() else {
+            return Ok(());
+        };
+        // `fuse_exit` alone only takes effect with the next request, detaching the mount point wakes the loop up.
+        // `fuse_unmount` would close the device fd while the loop may still read it.
+        fuse.exit();
+        if let Err(e) = detach_mount_point(&self.mount_point) {
+            self.session = Some((fuse, session_loop));
+            return Err(e.wrap_err(format!(
+                "leaving the session loop for '{}' running",
+                self.mount_point.display()
+            )));
+        }
+        Self::finish(&self.mount_point, fuse, session_loop)
+    }
+
+    /// Waits for the session loop to return, then closes the session and frees libfuse's state, which unregisters
+    /// the file system.
+    fn finish(
+        mount_point: &Path,
+        fuse: Arc<MountedFuse>,
+        session_loop: JoinHandle<i32>,
+    ) -> Result<()> {
+        let status = session_loop.join();
+
+        // the thread is joined, so ours is the last reference
+        let fuse =
+            Arc::into_inner(fuse).expect("session loop thread joined, but `fuse` is still shared");
+        fuse.unmount();
+        // SAFETY: the loop has returned, so no callback is running anymore
+        unsafe { fuse.destroy() };
+
+        match status {
+            Ok(0) => Ok(()),
+            Ok(errno) => bail!(
+                "session loop for '{}' returned non-zero status ({errno})",
+                mount_point.display()
+            ),
+            Err(panic) => bail!(
+                "session loop for '{}' panicked: {panic:?}",
+                mount_point.display()
+            ),
+        }
+    }
+}
+
+impl Drop for MountHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.unmount() {
+            error!("unmounting '{}': {e:#}", self.mount_point.display());
+        }
+        // detaching failed. The loop returns once the mount is gone some other way (e.g. `fusermount3 -u`) or with
+        // the next request (`fuse_exit`), so clean up then, without blocking here.
+        let Some((fuse, session_loop)) = self.session.take() else {
+            return;
+        };
+        let mount_point = self.mount_point.clone();
+        let cleanup = std::thread::Builder::new()
+            .name(format!("fuse '{}' cleanup", mount_point.display()))
+            .spawn(move || {
+                if let Err(e) = Self::finish(&mount_point, fuse, session_loop) {
+                    error!("unmounting '{}': {e:#}", mount_point.display());
+                }
+            });
+        if let Err(e) = cleanup {
+            error!(
+                "spawning the cleanup thread for '{}', leaking its session: {e:#}",
+                self.mount_point.display()
+            );
+        }
+    }
+}
+
+/// The operations table libfuse dispatches to, with every callback monomorphized for `FS`.
+fn fuse_operations<FS: Filesystem>() -> libfuse::fuse_operations {
+    libfuse::fuse_operations {
+        // connection setup
+        init: Some(init::<FS>),
+        destroy: Some(destroy::<FS>),
+
+        // elementary
+        getattr: Some(getattr::<FS>),
+        open: Some(open::<FS>),
+        read: Some(read::<FS>),
+        readdir: Some(readdir::<FS>),
+
+        // open file lifecycle
+        flush: Some(flush::<FS>),
+        release: Some(release::<FS>),
+        fsync: Some(fsync::<FS>),
+        opendir: Some(opendir::<FS>),
+        releasedir: Some(releasedir::<FS>),
+        fsyncdir: Some(fsyncdir::<FS>),
+
+        // writing
+        write: Some(write::<FS>),
+        create: Some(create::<FS>),
+        truncate: Some(truncate::<FS>),
+        unlink: Some(unlink::<FS>),
+
+        // directories
+        mkdir: Some(mkdir::<FS>),
+        rmdir: Some(rmdir::<FS>),
+        rename: Some(rename::<FS>),
+
+        // metadata
+        chmod: Some(chmod::<FS>),
+        chown: Some(chown::<FS>),
+        utimens: Some(utimens::<FS>),
+
+        statfs: Some(statfs::<FS>),
+
+        // extended attributes
+        getxattr: Some(getxattr::<FS>),
+        setxattr: Some(setxattr::<FS>),
+        listxattr: Some(listxattr::<FS>),
+        removexattr: Some(removexattr::<FS>),
+
+        // links
+        readlink: Some(readlink::<FS>),
+        symlink: Some(symlink::<FS>),
+        link: Some(link::<FS>),
+
+        // rest
+        mknod: None,
+        access: None,
+        lock: None,
+        bmap: None,
+        ioctl: None,
+        poll: None,
+        write_buf: None,
+        read_buf: None,
+        flock: None,
+        fallocate: None,
+        copy_file_range: None,
+        lseek: None,
+    }
+}
+
+/// Mount and library options passed to libfuse, see `mount.fuse3(8)` and the options of [`fuse_main`].
+///
+/// Unset options keep libfuse's defaults, except for `auto_unmount`, which is on by default.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct MountOptions {
+    // `default = false` is implied by fallback
+    /// Allow access by all users, not only the one who mounted.
+    #[builder(setter(strip_bool(fallback = toggle_allow_other)))]
+    pub allow_other: bool,
+    /// Allow access by the mounting user and root.
+    #[builder(setter(strip_bool(fallback = toggle_allow_root)))]
+    pub allow_root: bool,
+    /// Let the kernel check permissions against the reported modes, instead of leaving it to the file system.
+    #[builder(setter(strip_bool(fallback = toggle_default_permissions)))]
+    pub default_permissions: bool,
+    /// Unmount when the process exits, even if it doesn't unmount itself. On by default, as `fuse_main` always
+    /// mounted this way.
+    #[builder(default = true)]
+    pub auto_unmount: bool,
+    /// Mount read-only.
+    #[builder(setter(strip_bool(fallback = toggle_ro)))]
+    pub ro: bool,
+
+    /// Name of the source, shown in the first column of `/proc/mounts`.
+    #[builder(default, setter(strip_option, into))]
+    pub fsname: Option<String>,
+    /// Type shown as `fuse.<subtype>` in `/proc/mounts`.
+    #[builder(default, setter(strip_option, into))]
+    pub subtype: Option<String>,
+
+    /// Permission bits to clear from every reported mode.
+    #[builder(default, setter(strip_option))]
+    pub umask: Option<FilePermissions>,
+    /// Report every file as owned by this user.
+    #[builder(default, setter(strip_option))]
+    pub uid: Option<u32>,
+    /// Report every file as owned by this group.
+    #[builder(default, setter(strip_option))]
+    pub gid: Option<u32>,
+
+    /// How long the kernel caches name lookups.
+    #[builder(default, setter(strip_option))]
+    pub entry_timeout: Option<Duration>,
+    /// How long the kernel caches attributes.
+    #[builder(default, setter(strip_option))]
+    pub attr_timeout: Option<Duration>,
+    /// How long the kernel caches failed name lookups.
+    #[builder(default, setter(strip_option))]
+    pub negative_timeout: Option<Duration>,
+
+    /// Upper bound of bytes per read request.
+    #[builder(default, setter(strip_option))]
+    pub max_read: Option<u32>,
+
+    /// Further args handed to libfuse, e.g. [`ParsedArgs::fuse_args`].
+    ///
+    /// `-f` and `-s` are dropped: running in the foreground and single-threaded is up to the function mounting,
+    /// and `fuse_new` rejects them. `-d` (debug output) is passed on.
+    #[builder(default)]
+    pub args: Vec<String>,
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// `fuse_main` options that `fuse_new` doesn't know, see [`MountOptions::args`].
+const CMDLINE_ONLY_ARGS: [&str; 2] = ["-f", "-s"];
+
+impl MountOptions {
+    /// The options as command line args, i.e. a single `-o` followed by all set options, or nothing.
+    #[must_use]
+    pub fn to_args(&self) -> Vec<String> {
+        // `fuse_opt` splits at commas, with backslash as escape character
+        fn escape(value: &str) -> String {
+            value.replace('\\', "\\\\").replace(',', "\\,")
+        }
+
+        let flags = [
+            (self.allow_other, "allow_other"),
+            (self.allow_root, "allow_root"),
+            (self.default_permissions, "default_permissions"),
+            (self.auto_unmount, "auto_unmount"),
+            (self.ro, "ro"),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| name.to_owned());
+        let values = [
+            self.fsname
+                .as_deref()
+                .map(|v| format!("fsname={}", escape(v))),
+            self.subtype
+                .as_deref()
+                .map(|v| format!("subtype={}", escape(v))),
+            self.umask.map(|v| format!("umask={:o}", *v)),
+            self.uid.map(|v| format!("uid={v}")),
+            self.gid.map(|v| format!("gid={v}")),
+            self.entry_timeout
+                .map(|v| format!("entry_timeout={}", v.as_secs_f64())),
+            self.attr_timeout
+                .map(|v| format!("attr_timeout={}", v.as_secs_f64())),
+            self.negative_timeout
+                .map(|v| format!("negative_timeout={}", v.as_secs_f64())),
+            self.max_read.map(|v| format!("max_read={v}")),
+        ]
+        .into_iter()
+        .flatten();
+
+        let options = flags.chain(values).join(",");
+        let options = if options.is_empty() {
+            vec![]
+        } else {
+            vec!["-o".to_owned(), options]
+        };
+        options
+            .into_iter()
+            .chain(
+                self.args
+                    .iter()
+                    .filter(|arg| !CMDLINE_ONLY_ARGS.contains(&arg.as_str()))
+                    .cloned(),
+            )
+            .collect()
+    }
+}
+
+/// A `fuse_args` whose strings are copied into memory owned by libfuse, and freed on drop.
+struct FuseArgs(libfuse::fuse_args);
+
+impl FuseArgs {
+    fn new(args: impl IntoIterator<Item = impl AsRef<str>>) -> Result<Self> {
+        let mut fuse_args = Self(libfuse::fuse_args {
+            argc: 0,
+            argv: ptr::null_mut(),
+            allocated: 0,
+        });
+        for arg in args {
+            let arg = arg.as_ref();
+            let c_arg = CString::new(arg)
+                .map_err(Report::new)
+                .wrap_err_with(|| format!("Parsing arg '{arg}'"))?;
+            // SAFETY: `fuse_args.0` is valid, and libfuse copies `c_arg`
+            let errno = unsafe { libfuse::fuse_opt_add_arg(&raw mut fuse_args.0, c_arg.as_ptr()) };
+            if errno != 0 {
+                bail!("`libfuse::fuse_opt_add_arg()` failed on '{arg}' ({errno})");
+            }
+        }
+        Ok(fuse_args)
+    }
+
+    fn to_vec(&self) -> Result<Vec<String>> {
+        let argc = usize::try_from(self.0.argc).wrap_err("negative `argc`")?;
+        if argc == 0 {
+            return Ok(vec![]);
+        }
+        // SAFETY: libfuse keeps `argc` valid C string pointers in `argv`
+        let arg_ptrs = unsafe { std::slice::from_raw_parts(self.0.argv, argc) };
+        arg_ptrs
+            .iter()
+            .map(|&arg| {
+                // SAFETY: see above
+                let arg = unsafe { CStr::from_ptr(arg) };
+                arg.to_str()
+                    .map(ToOwned::to_owned)
+                    .map_err(Report::new)
+                    .wrap_err_with(|| format!("arg '{}' is not valid UTF-8", arg.to_string_lossy()))
+            })
+            .collect()
+    }
+}
+
+impl Drop for FuseArgs {
+    fn drop(&mut self) {
+        // SAFETY: only libfuse allocated the args, either in `fuse_opt_add_arg` or while parsing them
+        unsafe { libfuse::fuse_opt_free_args(&raw mut self.0) };
+    }
+}
+
+/// A file system specific `-o` option, one entry in the table handed to [`parse_fs_options`].
+pub struct FsOption<T> {
+    name: &'static str,
+    help: &'static str,
+    kind: FsOptionKind<T>,
+}
+
+enum FsOptionKind<T> {
+    Flag(fn(&mut T)),
+    Value {
+        value_name: &'static str,
+        set: fn(&mut T, &str) -> Result<()>,
+    },
+}
+
+impl<T> FsOption<T> {
+    /// `-o <name>`, calls `set` when present.
+    pub const fn flag(name: &'static str, help: &'static str, set: fn(&mut T)) -> Self {
+        Self {
+            name,
+            help,
+            kind: FsOptionKind::Flag(set),
+        }
+    }
+
+    /// `-o <name>=<value>`, calls `set` with the value when present. `value_name` is only shown in the help text.
+    pub const fn value(
+        name: &'static str,
+        value_name: &'static str,
+        help: &'static str,
+        set: fn(&mut T, &str) -> Result<()>,
+    ) -> Self {
+        Self {
+            name,
+            help,
+            kind: FsOptionKind::Value { value_name, set },
+        }
+    }
+
+    /// `fuse_opt` template: `name=%s` matches the name followed by any value, `name` only itself.
+    fn template(&self) -> String {
+        match self.kind {
+            FsOptionKind::Flag(_) => self.name.to_owned(),
+            FsOptionKind::Value { .. } => format!("{}=%s", self.name),
+        }
+    }
+}
+
+/// The args [`parse_fs_options`] didn't consume.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedArgs {
+    /// `-h` or `--help` was given, see [`print_help`].
+    pub show_help: bool,
+    /// Non-option args, usually just the mount point. Excludes the program name.
+    pub positional: Vec<String>,
+    /// Options unknown to the table, for libfuse. Pass them on via [`MountOptions::args`].
+    pub fuse_args: Vec<String>,
+}
+
+/// `fuse_opt` calls the processing function with this key for `-h` and `--help`. Table entries use their index.
+const HELP_KEY: i32 = i32::MAX;
+
+struct FsOptionParser<'a, T> {
+    table: &'a [FsOption<T>],
+    options: &'a mut T,
+    parsed: ParsedArgs,
+    error: Option<Report>,
+}
+
+/// Parses `args` (including the program name) with `fuse_opt_parse`: every `-o` option found in `table` is
+/// applied to `options` and removed, the others are left for libfuse in [`ParsedArgs::fuse_args`].
+///
+/// Options may be given separately (`-o backing=/srv/data -o cache_mb=256`) or comma separated
+/// (`-o backing=/srv/data,cache_mb=256`), commas inside values are escaped with a backslash.
+pub fn parse_fs_options<T>(
+    args: impl IntoIterator<Item = impl AsRef<str>>,
+    table: &[FsOption<T>],
+    options: &mut T,
+) -> Result<ParsedArgs> {
+    let templates = table
+        .iter()
+        .map(FsOption::template)
+        .chain(["-h".to_owned(), "--help".to_owned()])
+        .map(|template| CString::new(template).wrap_err("option name contains a nul byte"))
+        .collect::<Result<Vec<_>>>()?;
+    let keys = (0..table.len())
+        .map(|i| i32::try_from(i).expect("fewer than `i32::MAX` options"))
+        .chain([HELP_KEY, HELP_KEY]);
+    let fuse_opts = templates
+        .iter()
+        .zip(keys)
+        .map(|(template, key)| libfuse::fuse_opt {
+            templ: template.as_ptr(),
+            // `FUSE_OPT_KEY()`: the offset `-1U` makes libfuse call the processing function with `value` as key.
+            // note that it's the `unsigned int` -1, widened to `unsigned long`.
+            offset: u32::MAX.into(),
+            value: key,
+        })
+        .chain([libfuse::fuse_opt {
+            // `FUSE_OPT_END`
+            templ: ptr::null(),
+            offset: 0,
+            value: 0,
+        }])
+        .collect_vec();
+
+    let mut parser = FsOptionParser {
+        table,
+        options,
+        parsed: ParsedArgs::default(),
+        error: None,
+    };
+    let mut args = FuseArgs::new(args)?;
+    // SAFETY: `fuse_opts` is terminated by `FUSE_OPT_END` and its templates outlive the call, `parser` is what
+    // `process_fs_option::<T>` expects behind `data`.
+    let errno = unsafe {
+        libfuse::fuse_opt_parse(
+            &raw mut args.0,
+            (&raw mut parser).cast::<c_void>(),
+            fuse_opts.as_ptr(),
+            Some(process_fs_option::<T>),
+        )
+    };
+    if let Some(e) = parser.error {
+        return Err(e);
+    }
+    if errno != 0 {
+        bail!("`libfuse::fuse_opt_parse()` returned non-zero status ({errno}), see stderr");
+    }
+
+    let mut parsed = parser.parsed;
+    // skip the program name
+    parsed.fuse_args = args.to_vec()?.into_iter().skip(1).collect();
+    Ok(parsed)
+}
+
+/// `fuse_opt_proc_t` of [`parse_fs_options`]. Returns 0 to consume `arg`, 1 to keep it and -1 to abort.
+///
+/// # Safety
+///
+/// - `data` - points to a [`FsOptionParser<T>`] not otherwise borrowed
+/// - `arg` - is a valid pointer to a nul-terminated string
+unsafe extern "C" fn process_fs_option<T>(
+    data: *mut c_void,
+    arg: *const c_char,
+    key: i32,
+    _outargs: *mut libfuse::fuse_args,
+) -> i32 {
+    // SAFETY: as per our contract
+    let parser = unsafe { &mut *data.cast::<FsOptionParser<T>>() };
+    // SAFETY: as per our contract
+    let arg = match unsafe { CStr::from_ptr(arg) }.to_str() {
+        Ok(arg) => arg,
+        Err(e) => {
+            parser.error = Some(Report::new(e).wrap_err("arg is not valid UTF-8"));
+            return -1;
+        }
+    };
+
+    let result = std::panic::catch_unwind(core::panic::AssertUnwindSafe(|| match key {
+        HELP_KEY => {
+            parser.parsed.show_help = true;
+            Ok(0)
+        }
+        libfuse::FUSE_OPT_KEY_NONOPT => {
+            parser.parsed.positional.push(arg.to_owned());
+            Ok(0)
+        }
+        libfuse::FUSE_OPT_KEY_OPT => Ok(1),
+        key => {
+            let option = usize::try_from(key)
+                .ok()
+                .and_then(|i| parser.table.get(i))
+                .ok_or_else(|| color_eyre::eyre::eyre!("unexpected key {key} for '{arg}'"))?;
+            match option.kind {
+                FsOptionKind::Flag(set) => set(parser.options),
+                FsOptionKind::Value { set, .. } => {
+                    // the template matched, so `arg` starts with `name=`
+                    let value = &arg[option.name.len() + 1..];
+                    set(parser.options, value)
+                        .wrap_err_with(|| format!("invalid value for `-o {}`", option.name))?;
+                }
+            }
+            Ok(0)
+        }
+    }));
+    match result {
+        Ok(Ok(keep)) => keep,
+        Ok(Err(e)) => {
+            parser.error = Some(e);
+            -1
+        }
+        Err(panic) => {
+            parser.error = Some(color_eyre::eyre::eyre!(
+                "PANIC while parsing '{arg}':\n\n{panic:?}\n"
+            ));
+            -1
+        }
+    }
+}
+
+/// Help text for the options in `table`, formatted like libfuse's.
+#[must_use]
+pub fn fs_options_help<T>(table: &[FsOption<T>]) -> String {
+    table
+        .iter()
+        .map(|option| {
+            let usage = match option.kind {
+                FsOptionKind::Flag(_) => option.name.to_owned(),
+                FsOptionKind::Value { value_name, .. } => format!("{}={value_name}", option.name),
+            };
+            format!("    -o {usage:<19} {}\n", option.help)
+        })
+        .join("")
+}
+
+/// Prints the usage, the help for the options in `table`, and libfuse's help for its own options (see
+/// [`fuse_main`]), like `fuse_main` does with `-h`.
+pub fn print_help<T>(table: &[FsOption<T>]) -> Result<()> {
+    let program = program_name();
+    print!(
+        "usage: {program} [options] <mountpoint>\n\nFile system options:\n{}\nFUSE options:\n",
+        fs_options_help(table)
+    );
+    // libfuse prints through its own buffer
+    std::io::Write::flush(&mut std::io::stdout()).wrap_err("flushing stdout")?;
+    let mut args = FuseArgs::new([program])?;
+    // SAFETY: `args` is a valid `fuse_args`
+    unsafe {
+        libfuse::fuse_cmdline_help();
+        libfuse::fuse_lib_help(&raw mut args.0);
+    }
+    Ok(())
+}
+
+/// `argv[0]`, which libfuse shows in its usage and error messages.
+fn program_name() -> String {
+    std::env::args_os().next().map_or_else(
+        || env!("CARGO_PKG_NAME").to_owned(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(non_snake_case)]
+    use super::*;
+
+    #[test]
+    fn FileMode() {
+        assert_eq!(
+            TypedModeBuilder::builder()
+                .file_type(FileType::RegularFile)
+                .permissions(FilePermissions::new(0o775).unwrap())
+                .setuid()
+                .build()
+                .0,
+            libfuse::S_IFREG
+                + libfuse::S_IRWXU
+                + libfuse::S_IRWXG
+                + libfuse::S_IROTH
+                + libfuse::S_IXOTH
+        )
+    }
+
+    #[test]
+    fn FileMode_special_bits() {
+        let mode = TypedModeBuilder::builder()
+            .file_type(FileType::RegularFile)
+            .permissions(FilePermissions::new(0o775).unwrap())
+            .setuid()
+            .build();
+        assert_eq!(mode.0, libfuse::S_IFREG | libfuse::S_ISUID | 0o775);
+        assert!(mode.setuid() && !mode.setgid() && !mode.vtx_flag());
+    }
+
+    #[test]
+    fn FileMode_decode() {
+        let mode = FileMode::try_from(libfuse::S_IFDIR | 0o3755).unwrap();
+        assert_eq!(mode.file_type(), FileType::Directory);
+        assert_eq!(*mode.permissions(), 0o755);
+        assert!(!mode.setuid() && mode.setgid() && mode.vtx_flag());
+
+        let built = TypedModeBuilder::builder()
+            .file_type(mode.file_type())
+            .permissions(mode.permissions())
+            .setgid()
+            .vtx_flag()
+            .build();
+        assert_eq!(built, mode);
+
+        assert_eq!(
+            FileMode::try_from(0o644),
+            Err(FileModeError::UnknownFileType(0))
+        );
+        assert_eq!(
+            FileMode::try_from(libfuse::S_IFREG | 0o1_000_000),
+            Err(FileModeError::UnknownBits(libfuse::S_IFREG | 0o1_000_000))
+        );
+    }
+
+    #[test]
+    fn FilePermissions_symbolic() {
+        let parse = |s: &str| s.parse::<FilePermissions>().map(|p| *p);
+        assert_eq!(parse("rw-r--r--"), Ok(0o644));
+        assert_eq!(parse("u=rwx,go=rx"), Ok(0o755));
+        assert_eq!(parse("a=r,u+w"), Ok(0o644));
+        assert_eq!(parse("+x"), Ok(0o111));
+        assert_eq!(parse("-rw-rw-rw"), Ok(0));
+        assert_eq!(parse("rw-rw-rw-"), Ok(0o666));
+        assert_eq!(
+            parse("rwsr-xr-x"),
+            Err(ModeParseError::SpecialBits("rwsr-xr-x".into()))
+        );
+        assert_eq!(
+            parse("u*x"),
+            Err(ModeParseError::InvalidClause("u*x".into()))
+        );
+
+        let base = FilePermissions::new(0o664).unwrap();
+        assert_eq!(*base.apply_symbolic("go-w,u+x").unwrap(), 0o744);
+        assert_eq!(base.ls(), "rw-rw-r--");
+        assert_eq!(base.to_string(), 0o664.to_string());
+    }
+
+    #[test]
+    fn FileMode_symbolic() {
+        let mode: FileMode = "drwxr-sr-T".parse().unwrap();
+        assert_eq!(mode.file_type(), FileType::Directory);
+        assert!(!mode.setuid() && mode.setgid() && mode.vtx_flag());
+        assert_eq!(*mode.permissions(), 0o754);
+        assert_eq!(mode.to_string(), "drwxr-sr-T");
+
+        let mode = mode.apply_symbolic("o+X,g-s,u+s,o-t").unwrap();
+        assert_eq!(mode.to_string(), "drwsr-xr-x");
+        assert_eq!(
+            "-rw-r--r--"
+                .parse::<FileMode>()
+                .unwrap()
+                .apply_symbolic("a+X")
+                .unwrap()
+                .to_string(),
+            "-rw-r--r--"
+        );
+        assert!("xrw-r--r--".parse::<FileMode>().is_err());
+
+        let mode: FileMode = "u=rw,go=r".parse().unwrap();
+        assert_eq!(mode.to_string(), "-rw-r--r--");
+        assert_eq!(
+            mode.apply_symbolic("u+x").unwrap().to_string(),
+            "-rwxr--r--"
+        );
+    }
+
+    /// Stands in for libfuse's filler, `buf` points to a `(capacity, entries)` tuple.
+    unsafe extern "C" fn test_filler(
+        buf: *mut c_void,
+        name: *const c_char,
+        _stat: *const libfuse::stat,
+        offset: libfuse::off_t,
+        _flags: libfuse::fuse_fill_dir_flags,
+    ) -> i32 {
+        let (capacity, entries) = unsafe { &mut *buf.cast::<(usize, Vec<(String, i64)>)>() };
+        if entries.len() == *capacity {
+            return 1;
+        }
+        let name = unsafe { CStr::from_ptr(name) };
+        entries.push((name.to_str().unwrap().to_owned(), offset));
+        0
+    }
+
+    #[test]
+    fn readdir_paged() {
+        struct Dir;
+        impl Filesystem for Dir {
+            type FileHandle = ();
+            fn readdir(&self, _path: &Path) -> Result<ReaddirRetVal, Errno> {
+                Ok(ReaddirRetVal {
+                    entries: ["a", "b", "c"]
+                        .map(|name| DirEntryName::new(name).unwrap().into())
+                        .into(),
+                })
+            }
+        }
+
+        let mut buffer: (usize, Vec<(String, i64)>) = (2, Vec::new());
+        let mut list = |offset, dot_entries| {
+            buffer.1.clear();
+            let mut filler = DirFiller {
+                data_ptr: (&raw mut buffer).cast(),
+                filler_fn: test_filler,
+                full: false,
+                offset_shift: 0,
+                _buffer: std::marker::PhantomData,
+            };
+            let offset = if dot_entries {
+                filler.add_dot_entries(offset).unwrap()
+            } else {
+                offset
+            };
+            Dir.readdir_paged(Path::new("/"), offset, &mut filler)
+                .unwrap();
+            (
+                buffer
+                    .1
+                    .iter()
+                    .map(|(name, offset)| format!("{name}@{offset}"))
+                    .collect_vec(),
+                filler.full,
+            )
+        };
+
+        // "c" didn't fit, so the kernel asks again starting after "b"
+        assert_eq!(
+            list(0, false),
+            (vec!["a@1".to_owned(), "b@2".to_owned()], true)
+        );
+        assert_eq!(list(2, false), (vec!["c@3".to_owned()], false));
+
+        assert_eq!(
+            list(0, true),
+            (vec![".@1".to_owned(), "..@2".to_owned()], true)
+        );
+        assert_eq!(
+            list(2, true),
+            (vec!["a@3".to_owned(), "b@4".to_owned()], true)
+        );
+        assert_eq!(list(4, true), (vec!["c@5".to_owned()], false));
+    }
+
+    #[test]
+    fn ReadSize() {
+        assert!(super::ReadSize::new(0).is_err());
+        assert_eq!(super::ReadSize::new(4096).unwrap().get(), 4096);
+        assert!(super::ReadSize::new(i32::MAX as usize).is_ok());
+        assert_eq!(
+            super::ReadSize::new(i32::MAX as usize + 1)
+                .unwrap_err()
+                .to_string(),
+            "Argument out of range: '2147483648'. Must be in '1..=2147483647'"
+        );
+    }
+
+    #[test]
+    fn read_into() {
+        struct File;
+        impl Filesystem for File {
+            type FileHandle = ();
+            fn read(
+                &self,
+                _path: &Path,
+                _fh: Option<&()>,
+                _size: ReadSize,
+                offset: u64,
+            ) -> Result<ReadRetVal, Errno> {
+                // deliberately ignores `size`, to check the truncation
+                let content = b"hello world".get(offset as usize..).unwrap_or_default();
+                Ok(ReadRetVal {
+                    content: content.to_vec(),
+                })
+            }
+        }
+
+        let mut storage = [MaybeUninit::uninit(); 8];
+        let mut buf = ReadBuf::new(&mut storage);
+        File.read_into(Path::new("/"), None, &mut buf, 6).unwrap();
+        assert_eq!(buf.filled(), b"world");
+        assert_eq!(buf.remaining(), 3);
+        let mut buf = ReadBuf::new(&mut storage);
+        File.read_into(Path::new("/"), None, &mut buf, 0).unwrap();
+        assert_eq!(buf.filled(), b"hello wo");
+        assert_eq!(buf.put_slice(b"!"), 0);
+
+        let mut buf = ReadBuf::new(&mut storage);
+        assert_eq!(buf.put_slice(b"ab"), 2);
+        buf.unfilled()[0].write(b'c');
+        // SAFETY: initialized right above
+        unsafe { buf.advance(1) };
+        assert_eq!(buf.filled(), b"abc");
+    }
+
+    /// Logs the calls of the trampoline tests.
+    struct Files(std::sync::Mutex<Vec<String>>);
+    impl Filesystem for Files {
+        type FileHandle = u32;
+        fn create(
+            &self,
+            path: &Path,
+            mode: FileMode,
+            _flags: OpenFlags,
+        ) -> Result<OpenRetVal<u32>, Errno> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("create {} {mode}", path.display()));
+            Ok(OpenRetVal {
+                file_handle: 7,
+                fuse_file_info: Some(FuseFileInfo {
+                    direct_io: true,
+                    ..FuseFileInfo::default()
+                }),
+            })
+        }
+        fn write(
+            &self,
+            path: &Path,
+            fh: Option<&u32>,
+            data: &[u8],
+            offset: u64,
+        ) -> Result<WriteRetVal, Errno> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("write {} {fh:?} {offset}", path.display()));
+            // "/liar" claims to have written more than it got
+            let extra = usize::from(path == Path::new("/liar"));
+            Ok(WriteRetVal {
+                n_written: data.len() + extra,
+            })
+        }
+        fn truncate(&self, path: &Path, size: u64) -> Result<(), Errno> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("truncate {} {size}", path.display()));
+            Ok(())
+        }
+        fn unlink(&self, path: &Path) -> Result<(), Errno> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("unlink {}", path.display()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn create_write_truncate_unlink() {
+        register_fs(Files(std::sync::Mutex::default())).unwrap();
+
+        // SAFETY: plain C struct, all zeroes is valid
+        let mut fuse_file_info: libfuse::fuse_file_info = unsafe { std::mem::zeroed() };
+        let data = b"hello";
+        unsafe {
+            // no file type
+            assert_eq!(
+                create::<Files>(c"/a".as_ptr(), 0o644, &raw mut fuse_file_info),
+                -(Errno::EINVAL as i32)
+            );
+            assert_eq!(
+                create::<Files>(
+                    c"/a".as_ptr(),
+                    libfuse::S_IFREG | 0o644,
+                    &raw mut fuse_file_info
+                ),
+                0
+            );
+            assert_eq!(fuse_file_info.direct_io(), 1);
+            assert_eq!(borrow_file_handle::<u32>(&fuse_file_info).copied(), Some(7));
+
+            assert_eq!(
+                write::<Files>(
+                    c"/a".as_ptr(),
+                    data.as_ptr().cast(),
+                    data.len(),
+                    3,
+                    &raw mut fuse_file_info
+                ),
+                5
+            );
+            assert_eq!(
+                write::<Files>(
+                    c"/liar".as_ptr(),
+                    data.as_ptr().cast(),
+                    data.len(),
+                    0,
+                    &raw mut fuse_file_info
+                ),
+                -(Errno::EIO as i32)
+            );
+            assert_eq!(
+                write::<Files>(
+                    c"/a".as_ptr(),
+                    data.as_ptr().cast(),
+                    data.len(),
+                    -1,
+                    &raw mut fuse_file_info
+                ),
+                -(Errno::EINVAL as i32)
+            );
+
+            assert_eq!(
+                truncate::<Files>(c"/a".as_ptr(), -1, ptr::null_mut()),
+                -(Errno::EINVAL as i32)
+            );
+            assert_eq!(truncate::<Files>(c"/a".as_ptr(), 2, ptr::null_mut()), 0);
+            assert_eq!(unlink::<Files>(c"/a".as_ptr()), 0);
+            assert_eq!(release::<Files>(c"/a".as_ptr(), &raw mut fuse_file_info), 0);
+
+            // without a handle, like after `open` was skipped (`Capability::NoOpenSupport`)
+            assert_eq!(
+                write::<Files>(
+                    c"/b".as_ptr(),
+                    data.as_ptr().cast(),
+                    data.len(),
+                    0,
+                    &raw mut fuse_file_info
+                ),
+                5
+            );
+        }
+
+        let fs = fetch_fs_from_registry::<Files>().unwrap();
+        assert_eq!(
+            *fs.0.lock().unwrap(),
+            [
+                "create /a -rw-r--r--",
+                "write /a Some(7) 3",
+                "write /liar Some(7) 0",
+                "truncate /a 2",
+                "unlink /a",
+                "write /b None 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn call_into_user_code() {
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+        /// Counts `truncate` calls.
+        struct Counter(std::sync::atomic::AtomicUsize);
+        impl Filesystem for Counter {
+            type FileHandle = ();
+            fn truncate(&self, _path: &Path, _size: u64) -> Result<(), Errno> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+        /// Panics on `unlink`, and its file handles panic on `Drop`.
+        struct Shaky;
+        struct ShakyHandle;
+        impl Drop for ShakyHandle {
+            fn drop(&mut self) {
+                DROPPED.store(true, Ordering::SeqCst);
+                panic!("ShakyHandle dropped");
+            }
+        }
+        impl Filesystem for Shaky {
+            type FileHandle = ShakyHandle;
+            fn open(
+                &self,
+                _path: &Path,
+                _flags: OpenFlags,
+            ) -> Result<OpenRetVal<ShakyHandle>, Errno> {
+                Ok(OpenRetVal {
+                    file_handle: ShakyHandle,
+                    fuse_file_info: None,
+                })
+            }
+            fn truncate(&self, _path: &Path, _size: u64) -> Result<(), Errno> {
+                Ok(())
+            }
+            fn unlink(&self, _path: &Path) -> Result<(), Errno> {
+                panic!("unlink");
+            }
+        }
+        register_fs(Counter(std::sync::atomic::AtomicUsize::new(0))).unwrap();
+        register_fs(Shaky).unwrap();
+        let not_recoverable = -(Errno::ENOTRECOVERABLE as i32);
+
+        // SAFETY: plain C struct, all zeroes is valid
+        let mut fuse_file_info: libfuse::fuse_file_info = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { open::<Shaky>(c"/f".as_ptr(), &raw mut fuse_file_info) },
+            0
+        );
+
+        // worker threads, like `fuse_loop_mt` runs them
+        let workers = (0..8)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    (0..100).all(|_| unsafe {
+                        truncate::<Counter>(c"/f".as_ptr(), 1, ptr::null_mut()) == 0
+                    })
+                })
+            })
+            .collect_vec();
+
+        unsafe {
+            assert_eq!(truncate::<Shaky>(c"/f".as_ptr(), 1, ptr::null_mut()), 0);
+            assert_eq!(unlink::<Shaky>(c"/f".as_ptr()), not_recoverable);
+            assert_eq!(
+                truncate::<Shaky>(c"/f".as_ptr(), 1, ptr::null_mut()),
+                not_recoverable
+            );
+            // the handle is still dropped, without its panic unwinding any further
+            assert_eq!(
+                release::<Shaky>(c"/f".as_ptr(), &raw mut fuse_file_info),
+                not_recoverable
+            );
+        }
+        assert!(DROPPED.load(Ordering::SeqCst));
+
+        // the panic only poisoned `Shaky`
+        assert!(workers.into_iter().all(|worker| worker.join().unwrap()));
+        assert_eq!(
+            fetch_fs_from_registry::<Counter>()
+                .unwrap()
+                .0
+                .load(Ordering::SeqCst),
+            800
+        );
+        assert_eq!(
+            unsafe { truncate::<Counter>(c"/f".as_ptr(), 1, ptr::null_mut()) },
+            0
+        );
+    }
 
-/// usage: /home/ra1n/.cache/cargo_target/debug/examples/hello [options] <mountpoint>
-///
-/// FUSE options:
-///     -h   --help            print help
-///     -V   --version         print version
-///     -d   -o debug          enable debug output (implies -f)
-///     -f                     foreground operation
-///     -s                     disable multi-threaded operation
-///     -o clone_fd            use separate fuse device fd for each thread
-///                            (may improve performance)
-///     -o max_idle_threads    the maximum number of idle worker threads
-///                            allowed (default: -1)
-///     -o max_threads         the maximum number of worker threads
-///                            allowed (default: 10)
-///     -o kernel_cache        cache files in kernel
-///     -o [no]auto_cache      enable caching based on modification times (off)
-///     -o no_rofd_flush       disable flushing of read-only fd on close (off)
-///     -o umask=M             set file permissions (octal)
-///     -o fmask=M             set file permissions (octal)
-///     -o dmask=M             set dir  permissions (octal)
-///     -o uid=N               set file owner
-///     -o gid=N               set file group
-///     -o entry_timeout=T     cache timeout for names (1.0s)
-///     -o negative_timeout=T  cache timeout for deleted names (0.0s)
-///     -o attr_timeout=T      cache timeout for attributes (1.0s)
-///     -o ac_attr_timeout=T   auto cache timeout for attributes (attr_timeout)
-///     -o noforget            never forget cached inodes
-///     -o remember=T          remember cached inodes for T seconds (0s)
-///     -o modules=M1[:M2...]  names of modules to push onto filesystem stack
-///     -o allow_other         allow access by all users
-///     -o allow_root          allow access by root
-///     -o auto_unmount        auto unmount on process termination
-///
-/// Options for subdir module:
-///     -o subdir=DIR           prepend this directory to all paths (mandatory)
-///     -o [no]rellinks         transform absolute symlinks to relative
-///
-/// Options for iconv module:
-///     -o from_code=CHARSET   original encoding of file names (default: UTF-8)
-///     -o to_code=CHARSET     new encoding of the file names (default: UTF-8)
-///
-pub fn fuse_main<FS: Filesystem>(
-    fs: FS,
-    mount_point: impl AsRef<Path>,
-    args: impl Iterator<Item = impl AsRef<str>>,
-) -> Result<()> {
-    let Some(mount_point) = mount_point.as_ref().to_str() else {
-        bail!(
-            "mount point '{}' is not valid UTF-8",
-            mount_point.as_ref().to_string_lossy()
-        )
-    };
+    #[test]
+    fn MountedFuse() {
+        struct Mounted(u8);
+        impl Filesystem for Mounted {
+            type FileHandle = ();
+        }
+        let new = |fs, mount_point| {
+            super::MountedFuse::new(fs, Path::new(mount_point), &MountOptions::default())
+        };
+        let registered = || fetch_fs_from_registry::<Mounted>().map(|fs| fs.0);
+
+        // a mount of the same type is still registered, and stays so
+        register_fs(Mounted(1)).unwrap();
+        let error = new(Mounted(2), "/mnt").unwrap_err();
+        assert!(error.to_string().contains("already mounted"), "{error:#}");
+        assert_eq!(registered(), Ok(1));
+
+        unregister_fs::<Mounted>();
+        assert!(registered().is_err());
 
-    // we always run single-threaded, and in the foreground.
-    let mut args = args.map(|s| s.as_ref().to_owned()).collect_vec();
-    args.push("-f".into());
-    args.push("-s".into());
-    args.append(&mut vec!["-o".into(), "auto_unmount".into()]);
+        // rejected before anything is registered
+        assert!(new(Mounted(3), "/mnt\0").is_err());
+        assert!(registered().is_err());
 
-    let mut args = obtain_argv_as_mut_array(args.iter())
-        .wrap_err("converting `env::args()` to a mutable C string array `(*mut *mut c_char)`")?;
-    //let arg_ptrs = args.iter().map(|c_str| c_str.as_mut()).collect_vec();
+        register_fs(Mounted(4)).unwrap();
+        assert_eq!(registered(), Ok(4));
+    }
 
-    //let (argc, argv): (c_int, *mut *mut c_char) = { (args.len() as c_int, nix::libc::mall) };
+    #[test]
+    fn readlink() {
+        struct Link;
+        impl Filesystem for Link {
+            type FileHandle = ();
+            fn readlink(&self, _path: &Path) -> Result<ReadlinkRetVal, Errno> {
+                Ok(ReadlinkRetVal {
+                    target: "../target".into(),
+                })
+            }
+        }
+        register_fs(Link).unwrap();
 
-    // `fuse_main` automatically reads the mount point as positional arg.
-    //
-    // let mount_point_c_str = CString::new(mount_point)
-    //     .wrap_err_with(|| format!("mount point '{mount_point}' is not a valid CString"))?;
+        let read_link = |size| {
+            let mut buf = [0x7f_u8; 16];
+            let errno = unsafe {
+                super::readlink::<Link>(c"/link".as_ptr(), buf.as_mut_ptr().cast(), size)
+            };
+            (errno, buf)
+        };
 
-    state::register(fs);
+        // fits, including the terminator
+        let (errno, buf) = read_link(16);
+        assert_eq!(errno, 0);
+        assert_eq!(&buf[..10], b"../target\0");
+        // truncated to `size - 1` bytes, and still terminated, like `readlink(2)` does
+        let (errno, buf) = read_link(5);
+        assert_eq!(errno, 0);
+        assert_eq!(&buf[..6], b"../t\0\x7f");
+        let (errno, buf) = read_link(1);
+        assert_eq!(errno, 0);
+        assert_eq!(&buf[..2], b"\0\x7f");
+        // no space for the terminator
+        assert_eq!(read_link(0).0, -(Errno::EINVAL as i32));
+    }
 
-    let fuse_ops = libfuse::fuse_operations {
-        // elementary
-        getattr: Some(getattr::<FS>),
-        open: Some(open::<FS>),
-        read: Some(read::<FS>),
-        readdir: Some(readdir::<FS>),
+    #[test]
+    fn DirEntryName() {
+        assert_eq!(*super::DirEntryName::new("foo.txt").unwrap(), "foo.txt");
+        assert_eq!(super::DirEntryName::new(""), Err(DirEntryNameError::Empty));
+        assert_eq!(
+            "..".parse::<super::DirEntryName>(),
+            Err(DirEntryNameError::Reserved("..".into()))
+        );
+        assert_eq!(
+            "a/b".parse::<super::DirEntryName>(),
+            Err(DirEntryNameError::InvalidChar("a/b".into()))
+        );
+        assert!("x".repeat(255).parse::<super::DirEntryName>().is_ok());
+        assert_eq!(
+            Errno::from("x".repeat(256).parse::<super::DirEntryName>().unwrap_err()),
+            Errno::ENAMETOOLONG
+        );
+    }
 
-        // rest
-        readlink: None,
-        mknod: None,
-        mkdir: None,
-        unlink: None,
-        rmdir: None,
-        symlink: None,
-        rename: None,
-        link: None,
-        chmod: None,
-        chown: None,
-        truncate: None,
-        write: None,
-        statfs: None,
-        flush: None,
-        release: None,
-        fsync: None,
-        setxattr: None,
-        getxattr: None,
-        listxattr: None,
-        removexattr: None,
-        opendir: None,
-        releasedir: None,
-        fsyncdir: None,
-        init: None,
-        destroy: None,
-        access: None,
-        create: None,
-        lock: None,
-        utimens: None,
-        bmap: None,
-        ioctl: None,
-        poll: None,
-        write_buf: None,
-        read_buf: None,
-        flock: None,
-        fallocate: None,
-        copy_file_range: None,
-        lseek: None,
-    };
+    #[test]
+    fn ConnectionInfo() {
+        // SAFETY: plain C struct, all zeroes is valid
+        let mut conn: libfuse::fuse_conn_info = unsafe { std::mem::zeroed() };
+        conn.capable_ext = u64::from(libfuse::FUSE_CAP_READDIRPLUS | libfuse::FUSE_CAP_ASYNC_READ);
+        conn.want_ext = u64::from(libfuse::FUSE_CAP_ASYNC_READ);
 
-    unsafe {
-        // fuse_main_fn(argc: ::std::os::raw::c_int, argv: *mut *mut ::std::os::raw::c_char,
-        //              op: *const fuse_operations, user_data: *mut ::std::os::raw::c_void) -> ::std::os::raw::c_int
-        let errno = libfuse::fuse_main_fn(
-            args.len()
-                .try_into()
-                .unwrap_or_else(|_| panic!("more than {} args are not supported", i32::MAX)),
-            args.as_mut_ptr(),
-            &fuse_ops as *const libfuse::fuse_operations,
-            ptr::null_mut(),
+        let mut conn_info = super::ConnectionInfo::new(&conn);
+        assert_eq!(
+            conn_info.capable().iter().collect_vec(),
+            [Capability::AsyncRead, Capability::Readdirplus]
         );
-        if errno != 0 {
-            bail!("`libfuse::fuse_main_fn()` returned non-zero status ({errno})");
+        assert!(conn_info.want.contains(Capability::AsyncRead));
+        assert!(conn_info.want_if_capable(Capability::Readdirplus));
+        assert!(!conn_info.want_if_capable(Capability::WritebackCache));
+        // not capable, dropped by `apply_to`
+        conn_info.want.insert(Capability::NoOpenSupport);
+        conn_info.want.remove(Capability::AsyncRead);
+        conn_info.max_write = 1 << 20;
+        conn_info.apply_to(&mut conn);
+        assert_eq!(conn.want_ext, u64::from(libfuse::FUSE_CAP_READDIRPLUS));
+        assert_eq!(conn.max_write, 1 << 20);
+    }
+
+    #[test]
+    fn init() {
+        struct Plain;
+        impl Filesystem for Plain {
+            type FileHandle = ();
+        }
+        struct NoPlus;
+        impl Filesystem for NoPlus {
+            type FileHandle = ();
+            fn init(&self, conn: &mut super::ConnectionInfo, _cfg: &mut super::FuseConfig) {
+                conn.want.remove(Capability::Readdirplus);
+            }
         }
+        register_fs(Plain).unwrap();
+        register_fs(NoPlus).unwrap();
+
+        let plus = u64::from(libfuse::FUSE_CAP_READDIRPLUS);
+        let auto = u64::from(libfuse::FUSE_CAP_READDIRPLUS_AUTO);
+        // SAFETY: plain C structs, all zeroes is valid
+        let (mut conn, mut cfg): (libfuse::fuse_conn_info, libfuse::fuse_config) =
+            unsafe { (std::mem::zeroed(), std::mem::zeroed()) };
+        conn.capable_ext = plus | auto;
+
+        // libfuse's defaults are kept, even if they leave readdirplus out
+        conn.want_ext = auto;
+        unsafe { super::init::<Plain>(&raw mut conn, &raw mut cfg) };
+        assert_eq!(conn.want_ext, auto);
+        conn.want_ext = plus | auto;
+        unsafe { super::init::<Plain>(&raw mut conn, &raw mut cfg) };
+        assert_eq!(conn.want_ext, plus | auto);
+
+        // the file system can opt out
+        unsafe { super::init::<NoPlus>(&raw mut conn, &raw mut cfg) };
+        assert_eq!(conn.want_ext, auto);
     }
 
-    // let _fuse_args = libfuse::fuse_args {
-    //     argc: todo!(),
-    //     argv: todo!(),
-    //     allocated: todo!(),
-    // };
+    #[test]
+    fn DirEntryAttributes() {
+        /// `buf` points to a `Vec` of `(st_mode, flags)`, `0` if there is no `stat`.
+        unsafe extern "C" fn recording_filler(
+            buf: *mut c_void,
+            _name: *const c_char,
+            stat: *const libfuse::stat,
+            _offset: libfuse::off_t,
+            flags: libfuse::fuse_fill_dir_flags,
+        ) -> i32 {
+            let calls =
+                unsafe { &mut *buf.cast::<Vec<(FileModeRepr, libfuse::fuse_fill_dir_flags)>>() };
+            calls.push((
+                unsafe { stat.as_ref() }.map_or(0, |stat| stat.st_mode),
+                flags,
+            ));
+            0
+        }
 
-    // let _fuse_fs = unsafe {
-    //     libfuse::fuse_fs_new(&fuse_ops, std::mem::size_of_val(&fuse_ops), ptr::null_mut())
-    // };
-    //let fuse = unsafe { libfuse::_fuse_new_31(args, op, op_size, version, user_data) };
+        let mut calls: Vec<(FileModeRepr, libfuse::fuse_fill_dir_flags)> = Vec::new();
+        let mut filler = DirFiller {
+            data_ptr: (&raw mut calls).cast(),
+            filler_fn: recording_filler,
+            full: false,
+            offset_shift: 0,
+            _buffer: std::marker::PhantomData,
+        };
+        let file_mode = FileMode::try_from(libfuse::S_IFREG | 0o644).unwrap();
+        let stat = super::Stat::new_simple(file_mode, 1, 0).unwrap();
+        for (offset, entry) in [
+            DirEntry::from(super::DirEntryName::new("plain").unwrap()),
+            DirEntry::with_file_type(
+                super::DirEntryName::new("dir").unwrap(),
+                FileType::Directory,
+            ),
+            DirEntry::with_stat(super::DirEntryName::new("file").unwrap(), stat),
+        ]
+        .iter()
+        .enumerate()
+        {
+            filler.add(entry, offset as u64 + 1).unwrap();
+        }
 
-    // SAFETY: use mut_ptr() to not trust the c code to not mutate?
-    //let fuse_handle = unsafe { libfuse::fuse_mount(fuse_fs, mount_point_c_str.as_ptr()) };
+        assert_eq!(
+            calls,
+            [
+                (0, libfuse::fuse_fill_dir_flags_FUSE_FILL_DIR_DEFAULTS),
+                (
+                    libfuse::S_IFDIR,
+                    libfuse::fuse_fill_dir_flags_FUSE_FILL_DIR_DEFAULTS
+                ),
+                (
+                    libfuse::S_IFREG | 0o644,
+                    libfuse::fuse_fill_dir_flags_FUSE_FILL_DIR_PLUS
+                ),
+            ]
+        );
+    }
 
-    Ok(())
-}
+    #[test]
+    fn FuseConfig() {
+        // SAFETY: plain C struct, all zeroes is valid
+        let mut cfg: libfuse::fuse_config = unsafe { std::mem::zeroed() };
+        cfg.entry_timeout = 1.0;
+        cfg.attr_timeout = -1.0;
 
-fn obtain_argv_as_mut_array(
-    args: impl Iterator<Item = impl AsRef<str>>,
-) -> Result<ManuallyDrop<Box<[*mut c_char]>>> {
-    let c_string_vec = args
-        .map(|s| {
-            let s = s.as_ref();
-            CString::new(s)
-                .map_err(Report::new)
-                .wrap_err_with(|| format!("Parsing arg '{s}'"))
-        })
-        .collect::<Result<Vec<_>>>()
-        .wrap_err("converting args to CStrings")?;
+        let mut fuse_config = super::FuseConfig::new(&cfg);
+        assert_eq!(fuse_config.entry_timeout, Duration::from_secs(1));
+        assert_eq!(fuse_config.attr_timeout, Duration::ZERO);
+        assert!(!fuse_config.use_ino);
 
-    // we leak this memory, usually this should only be called once and leaking S(args) should not be considered relevant.
-    // On the other hand, this way C code can do almost anything to this data and we won't UB.
-    let ptr_vec = c_string_vec
-        .into_iter()
-        .map(|cstr| cstr.into_raw())
-        .collect_vec();
+        fuse_config.use_ino = true;
+        fuse_config.negative_timeout = Duration::from_millis(500);
+        cfg.nullpath_ok = 1;
+        fuse_config.apply_to(&mut cfg);
+        assert_eq!(cfg.use_ino, 1);
+        assert_eq!(cfg.nullpath_ok, 0);
+        assert!((cfg.negative_timeout - 0.5).abs() < f64::EPSILON);
+        assert!(cfg.attr_timeout.abs() < f64::EPSILON);
+    }
 
-    Ok(ManuallyDrop::new(ptr_vec.into_boxed_slice()))
-}
+    #[test]
+    fn FuseFileInfo() {
+        // SAFETY: `fuse_file_info` is plain old data, all-zero is a valid value
+        let mut fuse_file_info: libfuse::fuse_file_info = unsafe { std::mem::zeroed() };
+        fuse_file_info.set_keep_cache(1);
+        super::FuseFileInfo {
+            direct_io: true,
+            noflush: true,
+            ..Default::default()
+        }
+        .apply_to(&mut fuse_file_info);
 
-#[cfg(test)]
-mod tests {
-    #![allow(non_snake_case)]
-    use super::*;
+        assert_eq!(fuse_file_info.direct_io(), 1);
+        assert_eq!(fuse_file_info.noflush(), 1);
+        assert_eq!(fuse_file_info.keep_cache(), 0);
+        assert_eq!(fuse_file_info.nonseekable(), 0);
+    }
 
     #[test]
-    fn FileMode() {
+    fn FsOption() {
+        #[derive(Debug, Default)]
+        struct Options {
+            backing: String,
+            cache_mb: u32,
+            verbose: bool,
+        }
+        const TABLE: &[super::FsOption<Options>] = &[
+            super::FsOption::value("backing", "DIR", "backing directory", |o, v| {
+                o.backing = v.to_owned();
+                Ok(())
+            }),
+            super::FsOption::value("cache_mb", "N", "cache size", |o, v| {
+                o.cache_mb = v.parse()?;
+                Ok(())
+            }),
+            super::FsOption::flag("verbose", "log more", |o| o.verbose = true),
+        ];
+
+        let mut options = Options::default();
+        let mut parser = FsOptionParser {
+            table: TABLE,
+            options: &mut options,
+            parsed: ParsedArgs::default(),
+            error: None,
+        };
+        let mut process = |arg: &str, key| {
+            let arg = CString::new(arg).unwrap();
+            unsafe {
+                process_fs_option::<Options>(
+                    (&raw mut parser).cast(),
+                    arg.as_ptr(),
+                    key,
+                    ptr::null_mut(),
+                )
+            }
+        };
+        assert_eq!(process("backing=/srv/data", 0), 0);
+        assert_eq!(process("cache_mb=256", 1), 0);
+        assert_eq!(process("verbose", 2), 0);
+        assert_eq!(process("allow_other", libfuse::FUSE_OPT_KEY_OPT), 1);
+        assert_eq!(process("/mnt", libfuse::FUSE_OPT_KEY_NONOPT), 0);
+        assert_eq!(process("--help", HELP_KEY), 0);
+        assert_eq!(process("cache_mb=lots", 1), -1);
+        assert!(parser.error.is_some());
+        assert!(parser.parsed.show_help);
+        assert_eq!(parser.parsed.positional, ["/mnt"]);
+        assert_eq!(options.backing, "/srv/data");
+        assert_eq!(options.cache_mb, 256);
+        assert!(options.verbose);
+
         assert_eq!(
-            TypedModeBuilder::builder()
-                .file_type(FileType::RegularFile)
-                .permissions(FilePermissions::new(0o775).unwrap())
-                .setuid()
-                .build()
+            fs_options_help(TABLE),
+            "    -o backing=DIR         backing directory\n    \
+             -o cache_mb=N          cache size\n    \
+             -o verbose             log more\n"
+        );
+    }
+
+    #[test]
+    fn OpenFlags() {
+        let flags = super::OpenFlags(libc::O_RDONLY | libc::O_NOATIME);
+        assert_eq!(flags.access_mode(), AccessMode::ReadOnly);
+        assert!(flags.noatime() && !flags.append());
+        assert_eq!(flags.unknown_bits(), 0);
+
+        let flags = super::OpenFlags(libc::O_WRONLY | libc::O_DSYNC | O_LARGEFILE);
+        assert_eq!(flags.access_mode(), AccessMode::WriteOnly);
+        assert!(flags.dsync() && !flags.sync() && flags.largefile() && !flags.nofollow());
+
+        let flags = super::OpenFlags(libc::O_RDWR | libc::O_SYNC | 0o4_000_000_000);
+        assert!(flags.access_mode().is_readable() && flags.access_mode().is_writable());
+        assert!(flags.sync() && flags.dsync());
+        assert_eq!(flags.unknown_bits(), 0o4_000_000_000);
+
+        assert_eq!(
+            super::OpenFlags(libc::O_PATH | libc::O_DIRECTORY).access_mode(),
+            AccessMode::Search
+        );
+    }
+
+    #[test]
+    fn RenameFlags() {
+        let flags = RenameFlags::try_from(libc::RENAME_NOREPLACE).unwrap();
+        assert!(flags.noreplace() && !flags.exchange());
+        assert!(RenameFlags::try_from(0).is_ok());
+        assert_eq!(
+            RenameFlags::try_from(libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE),
+            Err(Errno::EINVAL)
+        );
+        assert_eq!(
+            RenameFlags::try_from(libc::RENAME_WHITEOUT),
+            Err(Errno::EINVAL)
+        );
+    }
+
+    #[test]
+    fn FileHandle() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        struct Handle(u32);
+        impl Drop for Handle {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        // SAFETY: `fuse_file_info` is plain old data
+        let mut fuse_file_info: libfuse::fuse_file_info = unsafe { std::mem::zeroed() };
+        assert!(unsafe { borrow_file_handle::<Handle>(&fuse_file_info) }.is_none());
+
+        store_file_handle(&mut fuse_file_info, Handle(42));
+        assert_eq!(
+            unsafe { borrow_file_handle::<Handle>(&fuse_file_info) }
+                .unwrap()
                 .0,
-            libfuse::S_IFREG
-                + libfuse::S_IRWXU
-                + libfuse::S_IRWXG
-                + libfuse::S_IROTH
-                + libfuse::S_IXOTH
-        )
+            42
+        );
+
+        drop(unsafe { take_file_handle::<Handle>(&mut fuse_file_info) });
+        assert!(unsafe { take_file_handle::<Handle>(&mut fuse_file_info) }.is_none());
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn TypedStatBuilder() {
+        let mode = FileMode::try_from(libfuse::S_IFREG | 0o644).unwrap();
+        let mtime = UNIX_EPOCH + Duration::new(1_700_000_000, 42);
+        let stat = TypedStatBuilder::builder()
+            .mode(mode)
+            .size(1025)
+            .uid(1000)
+            .gid(100)
+            .ino(7)
+            .mtime(mtime)
+            .blocks_from_size()
+            .build()
+            .unwrap();
+        assert_eq!((stat.st_uid, stat.st_gid, stat.st_ino), (1000, 100, 7));
+        assert_eq!(stat.st_nlink, 1);
+        assert_eq!(stat.st_blocks, 3);
+        assert_eq!(system_time_from_timespec(stat.st_mtim), Some(mtime));
+
+        let before_epoch = UNIX_EPOCH - Duration::new(1, 250_000_000);
+        let stat = TypedStatBuilder::builder()
+            .mode(mode)
+            .atime(before_epoch)
+            .build()
+            .unwrap();
+        assert_eq!(
+            (stat.st_atim.tv_sec, stat.st_atim.tv_nsec),
+            (-2, 750_000_000)
+        );
+
+        assert!(
+            TypedStatBuilder::builder()
+                .mode(mode)
+                .rdev(1)
+                .build()
+                .is_err()
+        );
+        assert!(
+            TypedStatBuilder::builder()
+                .mode(mode)
+                .blocks(1)
+                .blocks_from_size()
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn Stat() {
+        let path =
+            std::env::temp_dir().join(format!("rust-bindgen-fuse-test-{}", std::process::id()));
+        std::fs::write(&path, b"hello").unwrap();
+        std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o640))
+            .unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        let file_stat = nix::sys::stat::stat(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let from_metadata = Stat::try_from(metadata.clone()).unwrap();
+        let from_file_stat = Stat::from(file_stat);
+        for stat in [from_metadata, from_file_stat] {
+            assert_eq!(stat.file_type(), Ok(FileType::RegularFile));
+            assert_eq!(*stat.permissions(), 0o640);
+            assert_eq!(stat.size(), 5);
+            assert_eq!(stat.modified(), metadata.modified().ok());
+            assert_eq!(stat.accessed(), metadata.accessed().ok());
+            assert_eq!(stat.st_ino, file_stat.st_ino);
+        }
+    }
+
+    #[test]
+    fn StatFs() {
+        let builder = || {
+            TypedStatFsBuilder::builder()
+                .block_size(512)
+                .blocks(100)
+                .blocks_free(50)
+                .files(10)
+                .name_max(255)
+        };
+        let stat_fs = builder()
+            .blocks_available(40)
+            .files_free(5)
+            .build()
+            .unwrap();
+        assert_eq!(stat_fs.f_frsize, 512);
+        assert_eq!(stat_fs.f_bavail, 40);
+        assert_eq!(stat_fs.f_favail, 5);
+
+        assert_eq!(builder().build().unwrap().f_bavail, 50);
+        assert!(
+            TypedStatFsBuilder::builder()
+                .block_size(0)
+                .blocks(100)
+                .blocks_free(50)
+                .build()
+                .is_err()
+        );
+        assert!(builder().blocks_available(60).build().is_err());
+        assert!(builder().files_free(11).build().is_err());
+        assert_eq!(StatFs::unknown().f_namemax, 255);
+    }
+
+    #[test]
+    fn MountOptions() {
+        assert_eq!(
+            super::MountOptions::default().to_args(),
+            ["-o", "auto_unmount"]
+        );
+        assert!(
+            super::MountOptions::builder()
+                .auto_unmount(false)
+                .build()
+                .to_args()
+                .is_empty()
+        );
+
+        let options = super::MountOptions::builder()
+            .allow_other()
+            .fsname("a,b\\c")
+            .umask(FilePermissions::new(0o022).unwrap())
+            .uid(1000)
+            .attr_timeout(Duration::from_millis(1500))
+            .max_read(4096)
+            .build();
+        assert_eq!(
+            options.to_args(),
+            [
+                "-o",
+                "allow_other,auto_unmount,fsname=a\\,b\\\\c,umask=22,uid=1000,attr_timeout=1.5,max_read=4096"
+            ]
+        );
+
+        let options = super::MountOptions::builder()
+            .auto_unmount(false)
+            .toggle_ro(true)
+            .args(
+                ["-f", "-d", "-s", "-o", "fsname=x"]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .build();
+        assert_eq!(options.to_args(), ["-o", "ro", "-d", "-o", "fsname=x"]);
+    }
+
+    #[test]
+    fn XattrName() {
+        let name: XattrName = "user.mime_type".parse().unwrap();
+        assert_eq!(name.namespace(), XattrNamespace::User);
+        assert_eq!(name.name(), "mime_type");
+        assert_eq!(name.to_string(), "user.mime_type");
+
+        assert_eq!(
+            "foo.bar".parse::<XattrName>(),
+            Err(XattrNameError::UnknownNamespace("foo.bar".into()))
+        );
+        assert_eq!(
+            "trusted.".parse::<XattrName>(),
+            Err(XattrNameError::EmptyName)
+        );
+    }
+
+    #[test]
+    fn reply_with_size_probe() {
+        let data = b"user.a\0user.b\0";
+        let mut buf = [0 as c_char; 32];
+        let mut probe =
+            |size| unsafe { super::reply_with_size_probe(data, buf.as_mut_ptr(), size) };
+
+        assert_eq!(probe(0).unwrap(), 14);
+        assert_eq!(probe(13).unwrap_err().1, Errno::ERANGE);
+        assert_eq!(probe(32).unwrap(), 14);
+        assert_eq!(
+            buf[..14].iter().map(|&c| c.cast_unsigned()).collect_vec(),
+            data
+        );
+    }
+
+    #[test]
+    fn UtimeSpec() {
+        let timespec = |tv_sec, tv_nsec| libfuse::timespec { tv_sec, tv_nsec };
+        assert_eq!(
+            UtimeSpec::try_from(timespec(0, libc::UTIME_NOW)),
+            Ok(UtimeSpec::Now)
+        );
+        assert_eq!(
+            UtimeSpec::try_from(timespec(0, libc::UTIME_OMIT)),
+            Ok(UtimeSpec::Omit)
+        );
+        assert_eq!(
+            UtimeSpec::try_from(timespec(-1, 500_000_000)),
+            Ok(UtimeSpec::Time(UNIX_EPOCH - Duration::from_millis(500)))
+        );
+        assert_eq!(
+            UtimeSpec::try_from(timespec(0, 1_000_000_000)),
+            Err(Errno::EINVAL)
+        );
     }
 }